@@ -21,6 +21,7 @@ pub struct App {
     node_colors: Vec<Color>,
     tab: Box<dyn Tab>,
     current_tab: TabType,
+    is_paused: bool,
 }
 
 impl App {
@@ -30,6 +31,7 @@ impl App {
             node_colors: [Color::Black, Color::White].to_vec(),
             tab: Box::new(ModelTab::new()),
             current_tab: TabType::Model,
+            is_paused: false,
         }
     }
 
@@ -55,14 +57,13 @@ impl App {
 
     pub async fn handle_key_press(&mut self, key_ev: KeyEvent) -> Message {
         let mut sim = self.simulation_ctx.lock().await;
-        let model = &mut sim.model;
 
         match key_ev.code {
             key_code @ KeyCode::Esc => {
                 return match self.current_tab {
                     TabType::Model => {
                         if self.tab.is_modal_open() {
-                            self.tab.handle_key_press(key_code, model);
+                            self.tab.handle_key_press(key_code, &mut sim);
                             Message::None
                         } else {
                             Message::CloseApplication
@@ -75,14 +76,30 @@ impl App {
                 self.current_tab = self.current_tab.next();
                 self.tab = match self.current_tab {
                     TabType::Model => Box::new(ModelTab::new()),
-                    TabType::Graph => Box::new(GraphvizTab::new(model).unwrap()),
-                    TabType::Simulation => Box::new(SimulationTab::new(&sim.grid).unwrap()),
+                    TabType::Graph => Box::new(GraphvizTab::new(sim.model()).unwrap()),
+                    TabType::Simulation => Box::new(SimulationTab::new(sim.grid()).unwrap()),
                 };
             }
+            KeyCode::Backspace if self.current_tab == TabType::Simulation => {
+                // Stepping back pauses the run
+                self.is_paused = true;
+                return Message::StepBackSimulation;
+            }
+            KeyCode::Char(' ') if self.current_tab == TabType::Simulation => {
+                self.is_paused = !self.is_paused;
+                return match self.is_paused {
+                    true => Message::PauseSimulation,
+                    false => Message::ResumeSimulation,
+                };
+            }
+            KeyCode::Char('.') if self.current_tab == TabType::Simulation => {
+                return Message::StepSimulation;
+            }
             key_code => match self.current_tab {
-                TabType::Model => self.tab.handle_key_press(key_code, model),
+                TabType::Model | TabType::Simulation => {
+                    self.tab.handle_key_press(key_code, &mut sim)
+                }
                 TabType::Graph => todo!(),
-                TabType::Simulation => todo!(),
             },
         };
 
@@ -132,6 +149,7 @@ pub enum Message {
     ResumeSimulation,
     PauseSimulation,
     StepSimulation,
+    StepBackSimulation,
     UpdateModel(libca::Model),
     CloseApplication,
     None,
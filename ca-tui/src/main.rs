@@ -20,6 +20,9 @@ use libca::{
 use simulation::ThreadCommand;
 use tokio::{select, sync::Mutex};
 
+/// Generations that can be stepped back
+const HISTORY_LENGTH: usize = 256;
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let mut terminal = ratatui::init();
@@ -46,10 +49,9 @@ async fn main() -> anyhow::Result<()> {
         },
     ])?;
 
-    let simulation_ctx = Arc::new(Mutex::new(SimulationContext::new(
-        Model::game_of_life(),
-        grid,
-    )));
+    let mut simulation_ctx = SimulationContext::new(Model::game_of_life(), grid);
+    simulation_ctx.enable_history(HISTORY_LENGTH);
+    let simulation_ctx = Arc::new(Mutex::new(simulation_ctx));
 
     let task = tokio::spawn(simulation::simulation_thread(
         Arc::clone(&simulation_ctx),
@@ -82,6 +84,9 @@ async fn main() -> anyhow::Result<()> {
                     Message::StepSimulation => {
                         let _ = cmd_tx.send(ThreadCommand::Forward).await;
                     }
+                    Message::StepBackSimulation => {
+                        let _ = cmd_tx.send(ThreadCommand::StepBack).await;
+                    }
                     Message::UpdateModel(model) => todo!(),
                     Message::CloseApplication => should_quit = true,
                     Message::None => {}
@@ -15,6 +15,8 @@ pub enum ThreadCommand {
     Resume,
     Pause,
     Forward,
+    /// Pauses the run and undoes its last step
+    StepBack,
     // UpdateModel(libca::Model),
     SetGridItem {
         x: usize,
@@ -33,7 +35,7 @@ pub async fn simulation_thread(
     mut rx: Receiver<ThreadCommand>,
     tx: Sender<()>,
 ) {
-    let is_running = true;
+    let mut is_running = true;
 
     loop {
         if is_running {
@@ -44,11 +46,40 @@ pub async fn simulation_thread(
             let _ = tx.send(()).await;
 
             select! {
-                cmd = rx.recv() => {}
+                cmd = rx.recv() => {
+                    // Nobody is left to send commands once the channel closes
+                    let Some(cmd) = cmd else {
+                        break;
+                    };
+                    handle_command(&ctx, cmd, &mut is_running).await;
+                    let _ = tx.send(()).await;
+                }
                 _ = sleep(SIMULATION_DELTA) => {}
             }
         } else {
-            let cmd = rx.recv().await;
+            let Some(cmd) = rx.recv().await else {
+                break;
+            };
+            handle_command(&ctx, cmd, &mut is_running).await;
+            let _ = tx.send(()).await;
+        }
+    }
+}
+
+async fn handle_command(ctx: &Mutex<SimulationContext>, cmd: ThreadCommand, is_running: &mut bool) {
+    match cmd {
+        ThreadCommand::SetGridItem { x, y, state } => {
+            // Edits aimed past the edges, e.g. from a stale view of a resized
+            // grid, have nothing to change
+            let _ = ctx.lock().await.grid_mut().set(x, y, state);
+        }
+        ThreadCommand::Resume => *is_running = true,
+        ThreadCommand::Pause => *is_running = false,
+        ThreadCommand::Forward => ctx.lock().await.step(),
+        ThreadCommand::StepBack => {
+            *is_running = false;
+            // Nothing happens once the history runs out
+            ctx.lock().await.step_back();
         }
     }
 }
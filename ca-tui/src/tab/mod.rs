@@ -18,7 +18,7 @@ pub trait Tab {
         ctx: &mut Frame,
     );
 
-    fn handle_key_press(&mut self, key_code: KeyCode, model: &mut libca::Model) {}
+    fn handle_key_press(&mut self, key_code: KeyCode, simulation_ctx: &mut SimulationContext) {}
 
     fn is_modal_open(&self) -> bool {
         false
@@ -38,7 +38,8 @@ impl Tab for ModelTab {
         self.add_modal.is_some()
     }
 
-    fn handle_key_press(&mut self, key_code: KeyCode, model: &mut libca::Model) {
+    fn handle_key_press(&mut self, key_code: KeyCode, simulation_ctx: &mut SimulationContext) {
+        let model = simulation_ctx.model_mut();
         let (row, upper_bound, dependencies): (&mut _, _, &mut [_]) = match self.curr_panel {
             Panel::Nodes => (
                 &mut self.curr_node_row,
@@ -107,7 +108,7 @@ impl Tab for ModelTab {
         area: Rect,
         ctx: &mut Frame,
     ) {
-        let model = &simulation_ctx.model;
+        let model = simulation_ctx.model();
 
         let mut horizontal_layout = [
             Constraint::Fill(2),
@@ -380,6 +381,12 @@ impl ConditionWrapper<'_> {
                 let op_text = operand_to_simple_comparison_text(op);
                 format!("{prefix} there are {op_text} {abs} {node_name} neighbors")
             }
+            (left, right) => {
+                let rendered_op: &'static str = op.into();
+                let left = value_to_text(left, model);
+                let right = value_to_text(right, model);
+                format!("{prefix} {left} {rendered_op} {right}")
+            }
         };
 
         let text = Text::raw(text).style(style);
@@ -410,6 +417,21 @@ const fn operand_to_simple_comparison_text(op: Operand) -> &'static str {
     }
 }
 
+fn value_to_text(value: &libca::Value, model: &libca::Model) -> String {
+    use libca::Value::*;
+
+    match value {
+        Absolute(abs) => abs.to_string(),
+        PopulationCount(node_id) => format!("{} neighbors", node_id_to_name(node_id, model)),
+        LayerPopulationCount(layer, node_id) => format!(
+            "neighbors in state #{} on layer #{}",
+            node_id.as_index(),
+            layer.as_index()
+        ),
+        LayerState(layer) => format!("the state on layer #{}", layer.as_index()),
+    }
+}
+
 fn node_id_to_name<'m>(node_id: &libca::NodeId, model: &'m libca::Model) -> &'m str {
     model
         .get_node(node_id)
@@ -1,25 +1,63 @@
-use libca::{grid::Grid, simulation::SimulationContext};
+use crossterm::event::KeyCode;
+use libca::{
+    grid::{
+        neighbor_strategy::{is_upward_triangle, Boundary, NeighboringStrategy},
+        Axis, Grid,
+    },
+    simulation::SimulationContext,
+    NodeId,
+};
 use ratatui::{
     layout::{Constraint, Layout, Rect},
-    style::{Color, Stylize},
+    style::{Color, Style, Stylize},
     text::Line,
+    widgets::{Block, Clear, Paragraph},
     Frame,
 };
 
+use crate::widgets::Navbar;
+
 use super::Tab;
 
+const MAIN_AREA_AND_NAVBAR_CONSTRAINTS: [Constraint; 2] =
+    [Constraint::Min(0), Constraint::Length(1)];
+
 const HALF_BLOCK: &str = "▄";
+const UPWARD_TRIANGLE: &str = "▲";
+const DOWNWARD_TRIANGLE: &str = "▼";
+
+/// Controls of the run, handled by the app itself
+const RUN_KEYS: [(&str, &str); 3] = [
+    (" Space ", " Pause/Resume "),
+    (" . ", " Step "),
+    (" ⌫ ", " Step Back "),
+];
+
+/// Width of the panel explaining the selected cell
+const EXPLANATION_WIDTH: u16 = 48;
 
 pub struct SimulationTab {
     constraints: Vec<Constraint>,
+    /// Axis perpendicular to the slice shown for volumetric grids
+    axis: Axis,
+    /// Position of the slice along `axis`
+    position: usize,
+    /// Cell whose next state is being explained, as `(x, y)`
+    explained: Option<(usize, usize)>,
 }
 
 impl SimulationTab {
     pub fn new(grid: &Grid) -> anyhow::Result<Self> {
-        let height = grid.n_rows();
+        // Slices along X or Y stack every slice past `y`
+        let height = grid.slice_extents().into_iter().max().unwrap_or_default();
         let constraints = vec![Constraint::Length(1); height];
 
-        Ok(Self { constraints })
+        Ok(Self {
+            constraints,
+            axis: Axis::Z,
+            position: 0,
+            explained: None,
+        })
     }
 }
 
@@ -31,13 +69,87 @@ impl Tab for SimulationTab {
         area: Rect,
         ctx: &mut Frame,
     ) {
+        let [main_area, navbar_area] =
+            Layout::vertical(MAIN_AREA_AND_NAVBAR_CONSTRAINTS).areas(area);
+
         let layout = Layout::vertical(&self.constraints);
-        let sub_areas = layout.split(area);
+        let sub_areas = layout.split(main_area);
+
+        let grid = simulation_ctx.grid();
+        let slice = grid
+            .neighbor_ctx()
+            .is_volumetric()
+            .then(|| grid.slice(self.axis, self.position))
+            .flatten();
+        let (cells, cells_per_row) = match &slice {
+            Some(slice) => (slice.cells.as_slice(), slice.cells_per_row),
+            None => (grid.cells(), grid.cells_per_row()),
+        };
 
-        simulation_ctx
-            .grid
-            .cells()
-            .chunks(simulation_ctx.grid.cells_per_row())
+        match grid.neighbor_ctx().strategy() {
+            NeighboringStrategy::Triangle(_) => {
+                Self::draw_triangles(cells, cells_per_row, colors, &sub_areas, ctx)
+            }
+            _ => Self::draw_squares(cells, cells_per_row, colors, &sub_areas, ctx),
+        }
+
+        if let Some((x, y)) = self.explained {
+            Self::draw_explanation(simulation_ctx, x, y, main_area, ctx);
+        }
+        self.draw_navbar(simulation_ctx, navbar_area, ctx);
+    }
+
+    fn handle_key_press(&mut self, key_code: KeyCode, simulation_ctx: &mut SimulationContext) {
+        // Higher dimensional grids are browsed slice by slice along `z`
+        let [width, height, depth] = simulation_ctx.grid().slice_extents();
+        let axis_len = match self.axis {
+            Axis::X => width,
+            Axis::Y => height,
+            Axis::Z => depth,
+        };
+
+        match key_code {
+            KeyCode::Char('b') => {
+                let boundary = next_boundary(simulation_ctx.grid().boundary());
+                simulation_ctx
+                    .layers_mut()
+                    .for_each(|layer| layer.grid.set_boundary(boundary));
+            }
+            KeyCode::Char('a') => {
+                self.axis = next_axis(self.axis);
+                self.position = 0;
+            }
+            KeyCode::Char('[') => self.position = self.position.saturating_sub(1),
+            KeyCode::Char(']') => self.position = (self.position + 1).min(axis_len - 1),
+            KeyCode::Char('e') => {
+                self.explained = match self.explained {
+                    Some(_) => None,
+                    None => Some((width / 2, height / 2)),
+                };
+            }
+            key_code if let Some((x, y)) = &mut self.explained => match key_code {
+                KeyCode::Left => *x = x.saturating_sub(1),
+                KeyCode::Right => *x = (*x + 1).min(width - 1),
+                KeyCode::Up => *y = y.saturating_sub(1),
+                KeyCode::Down => *y = (*y + 1).min(height - 1),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl SimulationTab {
+    /// Packs two rows of cells in each line of text
+    fn draw_squares(
+        cells: &[NodeId],
+        cells_per_row: usize,
+        colors: &[Color],
+        areas: &[Rect],
+        ctx: &mut Frame,
+    ) {
+        cells
+            .chunks(cells_per_row)
             .map_windows(|[upper_line, lower_line]| {
                 upper_line
                     .iter()
@@ -49,7 +161,117 @@ impl Tab for SimulationTab {
                     })
                     .collect::<Line>()
             })
-            .zip(sub_areas.iter())
+            .zip(areas.iter())
             .for_each(|(line, area)| ctx.render_widget(line, *area));
     }
+
+    /// Draws one row of cells per line of text, alternating between upward
+    /// and downward glyphs
+    fn draw_triangles(
+        cells: &[NodeId],
+        cells_per_row: usize,
+        colors: &[Color],
+        areas: &[Rect],
+        ctx: &mut Frame,
+    ) {
+        cells
+            .chunks(cells_per_row)
+            .enumerate()
+            .map(|(y, line)| {
+                line.iter()
+                    .enumerate()
+                    .map(|(x, state)| {
+                        let glyph = if is_upward_triangle(x, y) {
+                            UPWARD_TRIANGLE
+                        } else {
+                            DOWNWARD_TRIANGLE
+                        };
+
+                        glyph.fg(colors[state.as_index()])
+                    })
+                    .collect::<Line>()
+            })
+            .zip(areas.iter())
+            .for_each(|(line, area)| ctx.render_widget(line, *area));
+    }
+
+    /// Shows why the cell at `(x, y)` is about to take its next state, in a
+    /// panel along the right edge
+    fn draw_explanation(
+        simulation_ctx: &SimulationContext,
+        x: usize,
+        y: usize,
+        area: Rect,
+        ctx: &mut Frame,
+    ) {
+        let text = match simulation_ctx.explain(x, y) {
+            Ok(explanation) => explanation.to_string(),
+            Err(err) => err.to_string(),
+        };
+
+        let [_, panel_area] =
+            Layout::horizontal([Constraint::Fill(1), Constraint::Length(EXPLANATION_WIDTH)])
+                .areas(area);
+        let block = Block::bordered()
+            .bg(Color::Black)
+            .border_style(Style::new().yellow())
+            .title(" Explanation ");
+
+        ctx.render_widget(Clear, panel_area);
+        ctx.render_widget(Paragraph::new(text).block(block), panel_area);
+    }
+
+    fn draw_navbar(&self, simulation_ctx: &SimulationContext, area: Rect, ctx: &mut Frame) {
+        let grid = simulation_ctx.grid();
+        let boundary: &'static str = grid.boundary().into();
+        let boundary = format!(" Boundary: {boundary} ");
+
+        if !grid.neighbor_ctx().is_volumetric() {
+            let explain = match self.explained {
+                Some((x, y)) => format!(" Explaining ({x}, {y}) "),
+                None => " Explain Cell ".to_string(),
+            };
+            let mut keys = vec![(" b ", boundary.as_str()), (" e ", &explain)];
+            if self.explained.is_some() {
+                keys.push((" ←↑↓→ ", " Select Cell "));
+            }
+            keys.extend(RUN_KEYS);
+
+            Navbar::draw(&keys, area, ctx);
+            return;
+        }
+
+        let axis: &'static str = self.axis.into();
+        let axis = format!(" Axis: {axis} ");
+        let slice = format!(" Slice: {} ", self.position);
+
+        let mut keys = vec![
+            (" b ", boundary.as_str()),
+            (" a ", &axis),
+            (" [ ] ", &slice),
+        ];
+        keys.extend(RUN_KEYS);
+
+        Navbar::draw(&keys, area, ctx);
+    }
+}
+
+fn next_axis(axis: Axis) -> Axis {
+    match axis {
+        Axis::X => Axis::Y,
+        Axis::Y => Axis::Z,
+        Axis::Z => Axis::X,
+    }
+}
+
+fn next_boundary(boundary: Boundary) -> Boundary {
+    match boundary {
+        Boundary::Open => Boundary::Toroidal,
+        Boundary::Toroidal => Boundary::Reflective,
+        Boundary::Reflective => Boundary::Fixed(NodeId::from_index(0)),
+        Boundary::Fixed(_) => Boundary::Cylinder,
+        Boundary::Cylinder => Boundary::Mobius,
+        Boundary::Mobius => Boundary::KleinBottle,
+        Boundary::KleinBottle => Boundary::Open,
+    }
 }
@@ -0,0 +1,279 @@
+use std::{io::Write, ops::Range, time::Duration};
+
+use anyhow::ensure;
+
+use crate::{
+    grid::Grid,
+    model::{Model, NodeId},
+    simulation::SimulationContext,
+};
+
+/// Writes the cells of a planar grid as a PNG, each one a block of
+/// `cell_size` by `cell_size` pixels in the colour the model gives its state
+pub fn write_png(
+    grid: &Grid,
+    model: &Model,
+    cell_size: u32,
+    writer: impl Write,
+) -> anyhow::Result<()> {
+    let frame = FrameLayout::new(grid, cell_size)?;
+
+    let mut encoder = png::Encoder::new(writer, frame.width, frame.height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()?
+        .write_image_data(&frame.rgb(grid, model))?;
+
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AnimationFormat {
+    /// Indexed colours, so models are limited to 256 states
+    #[default]
+    Gif,
+    Apng,
+}
+
+/// Captures a run as an animation, one frame every `every` generations
+/// within `steps`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Recorder {
+    pub format: AnimationFormat,
+    /// Generations to capture, the first one included and the last one left
+    /// out
+    pub steps: Range<u64>,
+    pub every: u64,
+    pub frame_delay: Duration,
+    /// Pixels along each side of a cell
+    pub cell_size: u32,
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Self {
+            format: AnimationFormat::default(),
+            steps: 0..100,
+            every: 1,
+            frame_delay: Duration::from_millis(100),
+            cell_size: 1,
+        }
+    }
+}
+
+impl Recorder {
+    /// Steps `ctx` through [`Recorder::steps`], writing the animation of its
+    /// main layer to `writer`, and returns how many frames it holds. Steps
+    /// before the range are run without being captured.
+    pub fn record(&self, ctx: &mut SimulationContext, writer: impl Write) -> anyhow::Result<u64> {
+        ensure!(self.every > 0, "frames must be at least 1 step apart");
+        ensure!(!self.steps.is_empty(), "there are no steps to record");
+        ensure!(
+            ctx.generation() <= self.steps.start,
+            "the run is already at generation {}, past the start of {:?}",
+            ctx.generation(),
+            self.steps
+        );
+        let frame = FrameLayout::new(ctx.grid(), self.cell_size)?;
+        let n_frames = (self.steps.end - self.steps.start).div_ceil(self.every);
+
+        while ctx.generation() < self.steps.start {
+            ctx.step();
+        }
+        let next_frame = |ctx: &mut SimulationContext, idx: u64| {
+            if idx > 0 {
+                (0..self.every).for_each(|_| ctx.step());
+            }
+        };
+
+        match self.format {
+            AnimationFormat::Gif => {
+                let palette = ctx.model().palette();
+                ensure!(
+                    palette.len() <= 256,
+                    "GIFs hold up to 256 colours, but the model has {} states",
+                    palette.len()
+                );
+                let flat_palette: Vec<u8> = palette
+                    .iter()
+                    .flat_map(|color| [color.0, color.1, color.2])
+                    .collect();
+                let (width, height) = (u16::try_from(frame.width)?, u16::try_from(frame.height)?);
+
+                let mut encoder = gif::Encoder::new(writer, width, height, &flat_palette)?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+                for idx in 0..n_frames {
+                    next_frame(ctx, idx);
+                    let indexes = frame.indexes(ctx.grid(), palette.len());
+                    encoder.write_frame(&gif::Frame {
+                        width,
+                        height,
+                        delay: (self.frame_delay.as_millis() / 10).try_into()?,
+                        buffer: indexes.into(),
+                        ..Default::default()
+                    })?;
+                }
+            }
+            AnimationFormat::Apng => {
+                let mut encoder = png::Encoder::new(writer, frame.width, frame.height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(n_frames.try_into()?, 0)?;
+                encoder.set_frame_delay(self.frame_delay.as_millis().try_into()?, 1000)?;
+
+                let mut writer = encoder.write_header()?;
+                for idx in 0..n_frames {
+                    next_frame(ctx, idx);
+                    writer.write_image_data(&frame.rgb(ctx.grid(), ctx.model()))?;
+                }
+                writer.finish()?;
+            }
+        }
+
+        Ok(n_frames)
+    }
+}
+
+/// Size of the picture of a grid, with each cell scaled up to a block of
+/// pixels
+struct FrameLayout {
+    width: u32,
+    height: u32,
+    cell_size: u32,
+}
+
+impl FrameLayout {
+    fn new(grid: &Grid, cell_size: u32) -> anyhow::Result<Self> {
+        ensure!(cell_size > 0, "cells must be at least 1 pixel wide");
+        ensure!(
+            grid.depth() == 1,
+            "only planar grids can be drawn, but the grid is {} slices deep",
+            grid.depth()
+        );
+
+        let scaled = |cells: usize| {
+            u32::try_from(cells)
+                .ok()
+                .and_then(|cells| cells.checked_mul(cell_size))
+                .ok_or_else(|| anyhow::anyhow!("the picture would be too large"))
+        };
+
+        Ok(Self {
+            width: scaled(grid.cells_per_row())?,
+            height: scaled(grid.n_rows())?,
+            cell_size,
+        })
+    }
+
+    /// State shown by each pixel, row by row. Cells missing from a short last
+    /// row are drawn in the first state.
+    fn pixels<'g>(&self, grid: &'g Grid) -> impl Iterator<Item = NodeId> + 'g {
+        let (width, height, cell_size) = (self.width, self.height, self.cell_size);
+        let cells_per_row = grid.cells_per_row();
+
+        (0..height).flat_map(move |y| {
+            (0..width).map(move |x| {
+                let idx = (y / cell_size) as usize * cells_per_row + (x / cell_size) as usize;
+                grid.cells().get(idx).copied().unwrap_or_default()
+            })
+        })
+    }
+
+    fn rgb(&self, grid: &Grid, model: &Model) -> Vec<u8> {
+        self.pixels(grid)
+            .map(|state| model.color(state))
+            .flat_map(|color| [color.0, color.1, color.2])
+            .collect()
+    }
+
+    /// Palette index of each pixel, where states past the palette take the
+    /// first colour
+    fn indexes(&self, grid: &Grid, palette_len: usize) -> Vec<u8> {
+        self.pixels(grid)
+            .map(|state| match state.as_index() {
+                idx if idx < palette_len => idx as u8,
+                _ => 0,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::test_utils::game_of_life_grid,
+        model::{NodeId, Rgb},
+    };
+
+    const GLIDER: &str = include_str!("../fixtures/gol/glider.txt");
+
+    #[test]
+    fn png_should_scale_cells_into_blocks() {
+        let grid = game_of_life_grid(GLIDER);
+        let mut model = Model::game_of_life();
+        model.set_color(NodeId(1), Rgb(255, 0, 0));
+
+        let mut png_bytes = Vec::new();
+        write_png(&grid, &model, 3, &mut png_bytes).unwrap();
+
+        let mut reader = png::Decoder::new(png_bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        let pixel_at = |x: usize, y: usize| &pixels[(y * 36 + x) * 3..][..3];
+
+        assert_eq!((info.width, info.height), (36, 36));
+        assert_eq!(pixel_at(6, 0), &[255, 0, 0]);
+        assert_eq!(pixel_at(2, 5), &[255, 0, 0]);
+        assert_eq!(pixel_at(3, 0), &[0, 0, 0]);
+    }
+
+    #[test]
+    fn gif_should_hold_every_kth_step_of_the_range() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(GLIDER));
+        let recorder = Recorder {
+            steps: 2..9,
+            every: 3,
+            ..Default::default()
+        };
+
+        let mut gif_bytes = Vec::new();
+        let n_frames = recorder.record(&mut ctx, &mut gif_bytes).unwrap();
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(gif_bytes.as_slice())
+            .unwrap();
+        let mut n_decoded = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert_eq!(frame.delay, 10);
+            n_decoded += 1;
+        }
+
+        // Generations 2, 5 and 8
+        assert_eq!((n_frames, n_decoded), (3, 3));
+        assert_eq!(ctx.generation(), 8);
+        assert!(recorder.record(&mut ctx, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn apng_should_declare_its_frames() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(GLIDER));
+        let recorder = Recorder {
+            format: AnimationFormat::Apng,
+            steps: 0..4,
+            cell_size: 2,
+            ..Default::default()
+        };
+
+        let mut apng_bytes = Vec::new();
+        recorder.record(&mut ctx, &mut apng_bytes).unwrap();
+
+        let reader = png::Decoder::new(apng_bytes.as_slice())
+            .read_info()
+            .unwrap();
+        let animation = reader.info().animation_control().unwrap();
+        assert_eq!(animation.num_frames, 4);
+        assert_eq!(reader.info().width, 24);
+    }
+}
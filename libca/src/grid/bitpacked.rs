@@ -0,0 +1,519 @@
+use std::iter::repeat_n;
+
+use rayon::{
+    iter::{IndexedParallelIterator, ParallelIterator},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    layer::CellContext,
+    model::{Model, NodeId},
+    state_map::StateMap,
+};
+
+use super::{
+    neighbor_strategy::{Boundary, NeighboringStrategy},
+    Grid,
+};
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+const DEAD: NodeId = NodeId(0);
+const ALIVE: NodeId = NodeId(1);
+
+const MAX_NEIGHBORS: usize = 8;
+
+/// Cells of each state with each number of live neighbors
+type Tally = [[u64; MAX_NEIGHBORS + 1]; 2];
+
+/// Next state of a two-state outer-totalistic model, as bitmasks indexed by
+/// the number of live neighbors
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct BinaryRule {
+    /// Dead cells with these many live neighbors come alive
+    born: u32,
+    /// Live cells with these many live neighbors stay alive
+    survive: u32,
+    n_neighbors: usize,
+    /// Index in the model of the edge fired by dead and live cells with each
+    /// number of live neighbors
+    fired: [[Option<usize>; MAX_NEIGHBORS + 1]; 2],
+}
+
+impl BinaryRule {
+    /// Tabulates `model` for every number of live neighbors, as long as it
+    /// only knows of [`DEAD`] and [`ALIVE`] cells, and its transitions only
+    /// look at how many neighbors are in each state.
+    ///
+    /// Cells missing some neighbors past an open boundary would see fewer
+    /// dead neighbors than the table accounts for, so models counting dead
+    /// cells are only tabulated when every cell has a full neighborhood.
+    pub(crate) fn from_model(
+        model: &Model,
+        n_neighbors: usize,
+        has_full_neighborhoods: bool,
+    ) -> Option<Self> {
+        let is_binary = model.nodes().map(|(id, _)| *id).eq([DEAD, ALIVE]);
+        if !is_binary || !model.is_outer_totalistic() {
+            return None;
+        }
+        if !has_full_neighborhoods && model.counted_states().contains(&DEAD) {
+            return None;
+        }
+
+        let mut state_map = StateMap::with_states(2);
+        let mut rule = Self {
+            born: 0,
+            survive: 0,
+            n_neighbors,
+            fired: [[None; MAX_NEIGHBORS + 1]; 2],
+        };
+
+        for n_alive in 0..=n_neighbors {
+            state_map.count_states(
+                repeat_n(ALIVE, n_alive).chain(repeat_n(DEAD, n_neighbors - n_alive)),
+            );
+            let cell = CellContext::new(0, &state_map, &[]);
+
+            for (curr_state, mask) in [(DEAD, &mut rule.born), (ALIVE, &mut rule.survive)] {
+                let fired = model.fired_edge(curr_state, &cell);
+                rule.fired[curr_state.as_index()][n_alive] = fired;
+                match fired.map_or(curr_state, |idx| *model.all_edges()[idx].to_node_id()) {
+                    ALIVE => *mask |= 1 << n_alive,
+                    DEAD => {}
+                    _ => return None,
+                }
+            }
+        }
+
+        Some(rule)
+    }
+}
+
+/// Two-state grid packing 64 cells in each word, surrounded by a frame of
+/// ghost cells standing for whatever lies past the boundary.
+///
+/// Cell `(x, y)` lives at bit `x + 1` of row `y + 1`.
+#[derive(Debug)]
+pub(crate) struct BitGrid {
+    width: usize,
+    height: usize,
+    words_per_row: usize,
+    /// Von Neumann neighborhood instead of Moore's
+    is_orthogonal: bool,
+    frame: Frame,
+    words: Vec<u64>,
+}
+
+/// What the ghost cells around a [`BitGrid`] hold
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Frame {
+    Dead,
+    Alive,
+    /// The cells along the opposite edge
+    Wrapped,
+}
+
+impl BitGrid {
+    /// Neighbors of each cell under `strategy`, when the fast path supports
+    /// it: only radius 1 Moore and von Neumann neighborhoods are
+    pub(crate) fn n_neighbors_of(strategy: &NeighboringStrategy) -> Option<usize> {
+        match strategy {
+            NeighboringStrategy::Square | NeighboringStrategy::VonNeumann { radius: 1 } => Some(4),
+            NeighboringStrategy::SquareAndCorners | NeighboringStrategy::Moore { radius: 1 } => {
+                Some(8)
+            }
+            _ => None,
+        }
+    }
+
+    /// Packs `grid`, or `None` when it doesn't fit the fast path: it must be
+    /// planar, fully rectangular, hold dead or live cells only, have a
+    /// radius 1 Moore or von Neumann neighborhood, and an open, toroidal or
+    /// fixed boundary
+    pub(crate) fn pack(grid: &Grid) -> Option<Self> {
+        let ctx = grid.neighbor_ctx();
+        let is_orthogonal = Self::n_neighbors_of(ctx.strategy())? == 4;
+        let frame = match ctx.boundary() {
+            Boundary::Open | Boundary::Fixed(DEAD) => Frame::Dead,
+            Boundary::Fixed(ALIVE) => Frame::Alive,
+            Boundary::Toroidal => Frame::Wrapped,
+            _ => return None,
+        };
+
+        let [width, height, _] = grid.slice_extents();
+        if ctx.is_volumetric() || width * height != grid.n_cells() {
+            return None;
+        }
+
+        let words_per_row = (width + 2).div_ceil(WORD_BITS);
+        let mut bit_grid = Self {
+            width,
+            height,
+            words_per_row,
+            is_orthogonal,
+            frame,
+            words: vec![0; words_per_row * (height + 2)],
+        };
+
+        for (y, row) in grid.cells().chunks(width).enumerate() {
+            for (x, cell) in row.iter().enumerate() {
+                match *cell {
+                    ALIVE => bit_grid.set(x + 1, y + 1),
+                    DEAD => {}
+                    _ => return None,
+                }
+            }
+        }
+
+        bit_grid.draw_frame();
+
+        Some(bit_grid)
+    }
+
+    #[inline]
+    pub(crate) fn n_neighbors(&self) -> usize {
+        if self.is_orthogonal {
+            4
+        } else {
+            8
+        }
+    }
+
+    /// Next generation, counting neighbors 64 cells at a time. With
+    /// `firings`, the firings of each edge of the model are added to it.
+    pub(crate) fn next(&self, rule: &BinaryRule, firings: Option<&mut [u64]>) -> Self {
+        debug_assert_eq!(rule.n_neighbors, self.n_neighbors());
+
+        let words_per_row = self.words_per_row;
+        let mut words = vec![0; self.words.len()];
+        // Bits from the east ghost cell on are only padding
+        let (last_word, end_bit) = ((self.width + 1) / WORD_BITS, (self.width + 1) % WORD_BITS);
+        let counts_firings = firings.is_some();
+
+        let tally = words[words_per_row..(self.height + 1) * words_per_row]
+            .par_chunks_mut(words_per_row)
+            .enumerate()
+            .map(|(y, next_row)| {
+                let mut tally = [[0; MAX_NEIGHBORS + 1]; 2];
+                let up = self.row(y);
+                let mid = self.row(y + 1);
+                let down = self.row(y + 2);
+
+                for (i, next_word) in next_row.iter_mut().enumerate() {
+                    let counts = if self.is_orthogonal {
+                        BitCounts::sum([up[i], west(mid, i), east(mid, i), down[i]])
+                    } else {
+                        BitCounts::sum([
+                            west(up, i),
+                            up[i],
+                            east(up, i),
+                            west(mid, i),
+                            east(mid, i),
+                            west(down, i),
+                            down[i],
+                            east(down, i),
+                        ])
+                    };
+
+                    *next_word = counts.apply(rule, mid[i]);
+
+                    if counts_firings {
+                        let cells = self.real_cells(i, last_word, end_bit);
+                        counts.tally(rule, mid[i], cells, &mut tally);
+                    }
+                }
+
+                next_row[0] &= !1;
+                next_row[last_word] &= (1 << end_bit) - 1;
+                next_row[last_word + 1..].fill(0);
+
+                tally
+            })
+            .reduce(
+                || [[0; MAX_NEIGHBORS + 1]; 2],
+                |mut total, tally| {
+                    for (total, tally) in total.iter_mut().flatten().zip(tally.iter().flatten()) {
+                        *total += tally;
+                    }
+                    total
+                },
+            );
+
+        if let Some(firings) = firings {
+            for (fired, tally) in rule.fired.iter().zip(&tally) {
+                for (edge_idx, count) in fired.iter().zip(tally) {
+                    if let Some(edge_idx) = edge_idx {
+                        firings[*edge_idx] += count;
+                    }
+                }
+            }
+        }
+
+        let mut next = Self { words, ..*self };
+        next.draw_frame();
+
+        next
+    }
+
+    /// Bits of the cells of the grid in the `i`th word of a row, leaving out
+    /// the ghost cells and the padding
+    #[inline]
+    fn real_cells(&self, i: usize, last_word: usize, end_bit: usize) -> u64 {
+        let mut cells = match i.cmp(&last_word) {
+            std::cmp::Ordering::Less => !0,
+            std::cmp::Ordering::Equal => (1 << end_bit) - 1,
+            std::cmp::Ordering::Greater => 0,
+        };
+        if i == 0 {
+            cells &= !1;
+        }
+
+        cells
+    }
+
+    /// Cells in index order
+    pub(crate) fn unpack(&self) -> Vec<NodeId> {
+        (1..=self.height)
+            .flat_map(|padded_y| {
+                (1..=self.width).map(move |padded_x| match self.get(padded_x, padded_y) {
+                    true => ALIVE,
+                    false => DEAD,
+                })
+            })
+            .collect()
+    }
+
+    #[inline]
+    fn row(&self, padded_y: usize) -> &[u64] {
+        &self.words[padded_y * self.words_per_row..(padded_y + 1) * self.words_per_row]
+    }
+
+    #[inline]
+    fn set(&mut self, padded_x: usize, padded_y: usize) {
+        self.words[padded_y * self.words_per_row + padded_x / WORD_BITS] |=
+            1 << (padded_x % WORD_BITS);
+    }
+
+    #[inline]
+    fn get(&self, padded_x: usize, padded_y: usize) -> bool {
+        self.row(padded_y)[padded_x / WORD_BITS] >> (padded_x % WORD_BITS) & 1 == 1
+    }
+
+    /// Sets the ghost cells, which must all be dead beforehand
+    fn draw_frame(&mut self) {
+        match self.frame {
+            Frame::Dead => {}
+            Frame::Alive => self.fill_frame(),
+            Frame::Wrapped => self.wrap_frame(),
+        }
+    }
+
+    /// Brings every ghost cell to life
+    fn fill_frame(&mut self) {
+        for padded_y in [0, self.height + 1] {
+            (0..self.width + 2).for_each(|padded_x| self.set(padded_x, padded_y));
+        }
+        for padded_y in 1..=self.height {
+            self.set(0, padded_y);
+            self.set(self.width + 1, padded_y);
+        }
+    }
+
+    /// Copies the opposite edges into the ghost cells
+    fn wrap_frame(&mut self) {
+        for padded_y in 1..=self.height {
+            if self.get(self.width, padded_y) {
+                self.set(0, padded_y);
+            }
+            if self.get(1, padded_y) {
+                self.set(self.width + 1, padded_y);
+            }
+        }
+
+        // Whole rows, so that corners come from the opposite corners
+        let words_per_row = self.words_per_row;
+        let last_row = self.height * words_per_row;
+        self.words
+            .copy_within(last_row..last_row + words_per_row, 0);
+        self.words.copy_within(
+            words_per_row..2 * words_per_row,
+            (self.height + 1) * words_per_row,
+        );
+    }
+}
+
+/// Bits of the cells to the west of those in `row[i]`
+#[inline]
+fn west(row: &[u64], i: usize) -> u64 {
+    let carry = if i > 0 {
+        row[i - 1] >> (WORD_BITS - 1)
+    } else {
+        0
+    };
+    (row[i] << 1) | carry
+}
+
+/// Bits of the cells to the east of those in `row[i]`
+#[inline]
+fn east(row: &[u64], i: usize) -> u64 {
+    let carry = row.get(i + 1).map_or(0, |word| word << (WORD_BITS - 1));
+    (row[i] >> 1) | carry
+}
+
+/// Live neighbor counts of 64 cells at once, sliced into one word per bit
+struct BitCounts([u64; 4]);
+
+impl BitCounts {
+    /// Adds up the neighbor words, as a ripple-carry adder working on every
+    /// bit position in parallel
+    #[inline]
+    fn sum<const N: usize>(neighbors: [u64; N]) -> Self {
+        let mut slices = [0; 4];
+
+        for neighbor in neighbors {
+            let mut carry = neighbor;
+            for slice in slices.iter_mut() {
+                let next_carry = *slice & carry;
+                *slice ^= carry;
+                carry = next_carry;
+            }
+        }
+
+        Self(slices)
+    }
+
+    /// Bits of the cells with exactly `count` live neighbors
+    #[inline]
+    fn equal_to(&self, count: usize) -> u64 {
+        self.0.iter().enumerate().fold(!0, |acc, (bit, slice)| {
+            if count >> bit & 1 == 1 {
+                acc & slice
+            } else {
+                acc & !slice
+            }
+        })
+    }
+
+    /// Adds the dead and live `cells` with each number of live neighbors to
+    /// `tally`
+    #[inline]
+    fn tally(&self, rule: &BinaryRule, alive: u64, cells: u64, tally: &mut Tally) {
+        let [dead_tally, alive_tally] = tally;
+        for (count, (dead_tally, alive_tally)) in dead_tally
+            .iter_mut()
+            .zip(alive_tally)
+            .enumerate()
+            .take(rule.n_neighbors + 1)
+        {
+            let matches = self.equal_to(count) & cells;
+            *dead_tally += (matches & !alive).count_ones() as u64;
+            *alive_tally += (matches & alive).count_ones() as u64;
+        }
+    }
+
+    #[inline]
+    fn apply(&self, rule: &BinaryRule, alive: u64) -> u64 {
+        (0..=rule.n_neighbors).fold(0, |next, count| {
+            let matches = self.equal_to(count);
+            let born = if rule.born >> count & 1 == 1 {
+                !alive & matches
+            } else {
+                0
+            };
+            let survive = if rule.survive >> count & 1 == 1 {
+                alive & matches
+            } else {
+                0
+            };
+
+            next | born | survive
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulation::SimulationContext;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use rstest::rstest;
+
+    /// Random soup wide enough to span several words per row
+    fn soup(strategy: NeighboringStrategy, boundary: Boundary) -> Grid {
+        let (width, height) = (131, 7);
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let mut grid = Grid::empty(width * height, width, strategy);
+        grid.set_boundary(boundary);
+        (0..width * height).for_each(|idx| grid.set_cell_at(idx, NodeId(rng.random_range(0..2))));
+
+        grid
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::SquareAndCorners, Boundary::Open)]
+    #[case(NeighboringStrategy::SquareAndCorners, Boundary::Toroidal)]
+    #[case(NeighboringStrategy::SquareAndCorners, Boundary::Fixed(ALIVE))]
+    #[case(NeighboringStrategy::Square, Boundary::Open)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 1 }, Boundary::Toroidal)]
+    #[case(NeighboringStrategy::Square, Boundary::Fixed(DEAD))]
+    fn bit_packed_path_should_match_the_generic_one(
+        #[case] strategy: NeighboringStrategy,
+        #[case] boundary: Boundary,
+    ) {
+        let model = Model::game_of_life();
+        let mut generic = soup(strategy.clone(), boundary);
+
+        let mut ctx = SimulationContext::new(Model::game_of_life(), soup(strategy, boundary));
+        assert!(BitGrid::pack(ctx.grid()).is_some());
+
+        for _ in 0..8 {
+            generic.map_cells(|cell, state_map| {
+                model.next_state(cell, &CellContext::new(0, state_map, &[]))
+            });
+            ctx.step();
+
+            assert!(ctx.grid().is_bit_packed());
+            assert_eq!(generic.cells(), ctx.grid().cells());
+        }
+    }
+
+    #[test]
+    fn edits_should_unpack_until_the_next_step() {
+        let strategy = NeighboringStrategy::SquareAndCorners;
+        let mut ctx =
+            SimulationContext::new(Model::game_of_life(), soup(strategy, Boundary::Toroidal));
+        ctx.step();
+        let generation = ctx.grid().cells().to_vec();
+
+        ctx.grid_mut().set_cell_at(0, ALIVE);
+        assert!(!ctx.grid().is_bit_packed());
+        assert_eq!(ctx.grid().cells()[1..], generation[1..]);
+        assert_eq!(ctx.grid().cells()[0], ALIVE);
+
+        ctx.step();
+        assert!(ctx.grid().is_bit_packed());
+    }
+
+    #[test]
+    fn binary_rule_should_tabulate_game_of_life() {
+        let rule = BinaryRule::from_model(&Model::game_of_life(), 8, false).unwrap();
+
+        assert_eq!(rule.born, 1 << 3);
+        assert_eq!(rule.survive, (1 << 2) | (1 << 3));
+    }
+
+    #[test]
+    fn unsupported_grids_should_not_be_packed() {
+        let mut grid = soup(NeighboringStrategy::SquareAndCorners, Boundary::Reflective);
+        assert!(BitGrid::pack(&grid).is_none());
+
+        grid.set_boundary(Boundary::Open);
+        grid.set_cell_at(0, NodeId(2));
+        assert!(BitGrid::pack(&grid).is_none());
+
+        let grid = soup(NeighboringStrategy::Moore { radius: 2 }, Boundary::Open);
+        assert!(BitGrid::pack(&grid).is_none());
+    }
+}
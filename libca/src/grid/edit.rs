@@ -0,0 +1,340 @@
+use std::fmt::Display;
+
+use crate::model::NodeId;
+
+use super::Grid;
+
+/// Why an edit couldn't be applied to a grid
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GridError {
+    /// The position lies past the edges of the grid
+    OutOfBounds {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+    },
+    /// Edits address cells by `(x, y)`, which is ambiguous on grids more
+    /// than one slice deep
+    Volumetric { depth: usize },
+}
+
+impl Display for GridError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GridError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            } => write!(f, "({x}, {y}) lies outside of the {width}x{height} grid"),
+            GridError::Volumetric { depth } => write!(
+                f,
+                "cells can't be edited by (x, y) on a grid {depth} slices deep"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GridError {}
+
+/// Coordinate-based editing of planar grids, with `(0, 0)` as the top-left
+/// cell. Edits never go through the boundary, and they reset the record of
+/// changed cells so that the next step evaluates the whole grid.
+impl Grid {
+    pub fn get(&self, x: usize, y: usize) -> Result<NodeId, GridError> {
+        self.index(x, y).map(|idx| self.cells()[idx])
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, state: NodeId) -> Result<(), GridError> {
+        let idx = self.index(x, y)?;
+        self.set_cell_at(idx, state);
+
+        Ok(())
+    }
+
+    /// Sets every cell of the `width` by `height` rectangle whose top-left
+    /// corner is at `(x, y)`. Nothing is changed unless it fits in the grid.
+    pub fn fill_rect(
+        &mut self,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        state: NodeId,
+    ) -> Result<(), GridError> {
+        if width == 0 || height == 0 {
+            return self.index(x, y).map(|_| ());
+        }
+        self.index(x, y)?;
+        self.index(x.saturating_add(width - 1), y.saturating_add(height - 1))?;
+
+        for row in y..y + height {
+            let start = row * self.cells_per_row() + x;
+            self.cells_mut()[start..start + width].fill(state);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the cells along the line from `from` to `to`, both included, as
+    /// traced by Bresenham's algorithm
+    pub fn draw_line(
+        &mut self,
+        from: (usize, usize),
+        to: (usize, usize),
+        state: NodeId,
+    ) -> Result<(), GridError> {
+        self.index(from.0, from.1)?;
+        self.index(to.0, to.1)?;
+
+        let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+        let (to_x, to_y) = (to.0 as isize, to.1 as isize);
+        let (dx, dy) = ((to_x - x).abs(), -(to_y - y).abs());
+        let (step_x, step_y) = ((to_x - x).signum(), (to_y - y).signum());
+        let mut error = dx + dy;
+
+        loop {
+            self.set(x as usize, y as usize, state)?;
+            if (x, y) == (to_x, to_y) {
+                return Ok(());
+            }
+
+            let double_error = 2 * error;
+            if double_error >= dy {
+                error += dy;
+                x += step_x;
+            }
+            if double_error <= dx {
+                error += dx;
+                y += step_y;
+            }
+        }
+    }
+
+    /// Sets the region of cells sharing the state of `(x, y)` and connected
+    /// to it through their sides, and returns how many cells it holds
+    pub fn flood_fill(&mut self, x: usize, y: usize, state: NodeId) -> Result<usize, GridError> {
+        let start = self.index(x, y)?;
+        let target = self.cells()[start];
+        if target == state {
+            return Ok(0);
+        }
+
+        let width = self.cells_per_row();
+        let mut n_filled = 0;
+        let mut pending = vec![start];
+        let cells = self.cells_mut();
+        // The last row may be cut short
+        let n_cells = cells.len();
+
+        while let Some(idx) = pending.pop() {
+            if cells[idx] != target {
+                continue;
+            }
+            cells[idx] = state;
+            n_filled += 1;
+
+            let (x, y) = (idx % width, idx / width);
+            if x > 0 {
+                pending.push(idx - 1);
+            }
+            if x + 1 < width && idx + 1 < n_cells {
+                pending.push(idx + 1);
+            }
+            if y > 0 {
+                pending.push(idx - width);
+            }
+            if idx + width < n_cells {
+                pending.push(idx + width);
+            }
+        }
+
+        Ok(n_filled)
+    }
+
+    /// Copies every cell of `source` into this grid, with its top-left corner
+    /// at `(x, y)`. Nothing is changed unless it fits in the grid.
+    pub fn paste(&mut self, source: &Grid, x: usize, y: usize) -> Result<(), GridError> {
+        source.ensure_planar()?;
+        let width = source.cells_per_row();
+        self.index(x, y)?;
+        if source.n_cells() == 0 {
+            return Ok(());
+        }
+        // Both the first row and the last cell must fit, which may each be
+        // the widest reach when either grid's last row is cut short
+        let (last_x, last_y) = (
+            (source.n_cells() - 1) % width,
+            (source.n_cells() - 1) / width,
+        );
+        self.index(x.saturating_add(width - 1), y)?;
+        self.index(x.saturating_add(last_x), y.saturating_add(last_y))?;
+
+        let cells_per_row = self.cells_per_row();
+        let cells = self.cells_mut();
+        for (row, source_row) in (y..).zip(source.cells().chunks(width)) {
+            let start = row * cells_per_row + x;
+            cells[start..start + source_row.len()].copy_from_slice(source_row);
+        }
+
+        Ok(())
+    }
+
+    /// Index of the cell at `(x, y)`
+    pub fn index(&self, x: usize, y: usize) -> Result<usize, GridError> {
+        self.ensure_planar()?;
+
+        let width = self.cells_per_row();
+        let height = self.n_rows();
+        // The last row may be cut short
+        match y * width + x {
+            idx if x < width && y < height && idx < self.n_cells => Ok(idx),
+            _ => Err(GridError::OutOfBounds {
+                x,
+                y,
+                width,
+                height,
+            }),
+        }
+    }
+
+    fn ensure_planar(&self) -> Result<(), GridError> {
+        match self.depth() {
+            1 => Ok(()),
+            depth => Err(GridError::Volumetric { depth }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{
+        neighbor_strategy::NeighboringStrategy,
+        test_utils::{game_of_life_grid, to_game_of_life_output},
+    };
+
+    const EMPTY: &str = "
+        ░░░░░
+        ░░░░░
+        ░░░░░
+        ░░░░░
+    ";
+
+    #[test]
+    fn cells_past_a_short_last_row_should_be_out_of_bounds() {
+        let mut grid = Grid::empty(7, 3, NeighboringStrategy::SquareAndCorners);
+
+        assert_eq!(grid.set(0, 2, NodeId(1)), Ok(()));
+        assert_eq!(
+            grid.set(1, 2, NodeId(1)),
+            Err(GridError::OutOfBounds {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 3,
+            })
+        );
+        assert!(grid.get(2, 2).is_err());
+        assert!(grid.fill_rect(0, 2, 2, 1, NodeId(1)).is_err());
+        assert_eq!(grid.population(), [(NodeId(0), 6), (NodeId(1), 1)].into());
+
+        assert!(grid
+            .paste(&Grid::empty(4, 2, NeighboringStrategy::Square), 0, 1)
+            .is_err());
+        assert_eq!(grid.flood_fill(1, 1, NodeId(1)), Ok(6));
+        assert_eq!(grid.population(), [(NodeId(1), 7)].into());
+
+        let mut source = Grid::empty(3, 2, NeighboringStrategy::Square);
+        source.set_cell_at(2, NodeId(1));
+        assert_eq!(
+            grid.paste(&source, 1, 1),
+            Err(GridError::OutOfBounds {
+                x: 1,
+                y: 2,
+                width: 3,
+                height: 3,
+            })
+        );
+        assert_eq!(grid.paste(&source, 0, 1), Ok(()));
+        assert_eq!(grid.population(), [(NodeId(0), 2), (NodeId(1), 5)].into());
+    }
+
+    #[test]
+    fn out_of_range_edits_should_be_rejected() {
+        let mut grid = game_of_life_grid(EMPTY);
+        let out_of_bounds = GridError::OutOfBounds {
+            x: 5,
+            y: 1,
+            width: 5,
+            height: 4,
+        };
+
+        assert_eq!(grid.set(5, 1, NodeId(1)), Err(out_of_bounds.clone()));
+        assert_eq!(
+            grid.fill_rect(2, 1, 4, 2, NodeId(1)),
+            Err(GridError::OutOfBounds {
+                x: 5,
+                y: 2,
+                width: 5,
+                height: 4,
+            })
+        );
+        assert_eq!(
+            grid.draw_line((0, 0), (5, 1), NodeId(1)),
+            Err(out_of_bounds)
+        );
+        assert_eq!(grid.population(), [(NodeId(0), 20)].into());
+
+        let volumetric = Grid::with_extents(&[2, 2, 2], NeighboringStrategy::Moore { radius: 1 });
+        assert_eq!(
+            volumetric.unwrap().get(0, 0),
+            Err(GridError::Volumetric { depth: 2 })
+        );
+    }
+
+    #[test]
+    fn lines_and_rectangles_should_be_drawn_inclusively() {
+        let mut grid = game_of_life_grid(EMPTY);
+
+        grid.draw_line((0, 3), (4, 0), NodeId(1)).unwrap();
+        grid.fill_rect(3, 2, 2, 2, NodeId(1)).unwrap();
+
+        assert_eq!(to_game_of_life_output(&grid), "░░░░█\n░░██░\n░█░██\n█░░██");
+    }
+
+    #[test]
+    fn flood_fill_should_stop_at_other_states() {
+        let mut grid = game_of_life_grid(
+            "
+            ░░█░░
+            ░░█░░
+            ███░░
+            ░░█░░
+        ",
+        );
+
+        assert_eq!(grid.flood_fill(0, 0, NodeId(1)), Ok(4));
+        assert_eq!(grid.flood_fill(0, 3, NodeId(0)), Ok(0));
+        assert_eq!(to_game_of_life_output(&grid), "███░░\n███░░\n███░░\n░░█░░");
+    }
+
+    #[test]
+    fn paste_should_copy_the_sub_grid_at_an_offset() {
+        let mut grid = game_of_life_grid(EMPTY);
+        let glider = game_of_life_grid(
+            "
+            ░█░
+            ░░█
+            ███
+        ",
+        );
+
+        assert!(grid.paste(&glider, 3, 1).is_err());
+        grid.paste(&glider, 2, 1).unwrap();
+
+        assert_eq!(grid.get(4, 3), Ok(NodeId(1)));
+        assert_eq!(to_game_of_life_output(&grid), "░░░░░\n░░░█░\n░░░░█\n░░███");
+    }
+}
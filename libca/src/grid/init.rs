@@ -0,0 +1,418 @@
+use rand::{seq::IndexedRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::model::NodeId;
+
+use super::{Axis, Grid, StateProbabilty};
+
+/// How the cells of a grid are laid out before the first step.
+///
+/// Layouts apply to every slice of volumetric grids alike, while random
+/// draws are made for each cell.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Initializer {
+    /// Every cell drawn from the weighted `states`
+    Random { states: Vec<StateProbabilty> },
+    /// A single cell in `state` at the center of the grid
+    SingleCell { state: NodeId, background: NodeId },
+    /// A `width` by `height` block in `state` at the center of the grid
+    CentralBlock {
+        width: usize,
+        height: usize,
+        state: NodeId,
+        background: NodeId,
+    },
+    /// Cells of the `width` by `height` rectangle whose top-left corner is at
+    /// `(x, y)` drawn from the weighted `states`
+    SoupInRect {
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        states: Vec<StateProbabilty>,
+        background: NodeId,
+    },
+    /// Cells within `radius` of `(x, y)` drawn from the weighted `states`
+    SoupInDisc {
+        x: usize,
+        y: usize,
+        radius: f32,
+        states: Vec<StateProbabilty>,
+        background: NodeId,
+    },
+    /// Bands `width` cells wide going through `states` in turn along `axis`
+    Stripes {
+        axis: Axis,
+        width: usize,
+        states: Vec<NodeId>,
+    },
+    /// Squares of `size` by `size` cells alternating between two states
+    Checkerboard { size: usize, states: [NodeId; 2] },
+    /// Patches of the weighted `states` about `correlation_length` cells
+    /// across, which are single cells at a length of 0
+    Noise {
+        correlation_length: f32,
+        states: Vec<StateProbabilty>,
+    },
+}
+
+/// Initializer and seed that laid out a grid, which lays it out again exactly
+/// when passed back to [`Grid::initialize`]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Initialization {
+    pub initializer: Initializer,
+    pub seed: u64,
+}
+
+/// Smoothing passes of the noise initializer. Three box blurs in a row come
+/// close to a gaussian one.
+const NOISE_BLUR_PASSES: usize = 3;
+
+impl Grid {
+    /// Lays out every cell with `initializer`, drawing random cells from a
+    /// generator seeded with `seed`, and records both in
+    /// [`Grid::initialization`]
+    pub fn initialize(&mut self, initializer: Initializer, seed: u64) -> anyhow::Result<()> {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let [width, height, _] = self.slice_extents();
+        let (center_x, center_y) = (width / 2, height / 2);
+        let positions = (0..self.n_cells).map(|idx| self.neighbor_ctx.coords(idx));
+
+        let cells = match &initializer {
+            Initializer::Random { states } => positions
+                .map(|_| draw(states, &mut rng))
+                .collect::<anyhow::Result<_>>()?,
+            Initializer::SingleCell { state, background } => positions
+                .map(|(x, y, _)| {
+                    if (x, y) == (center_x, center_y) {
+                        *state
+                    } else {
+                        *background
+                    }
+                })
+                .collect(),
+            Initializer::CentralBlock {
+                width: block_width,
+                height: block_height,
+                state,
+                background,
+            } => {
+                let left = center_x.saturating_sub(block_width / 2);
+                let top = center_y.saturating_sub(block_height / 2);
+                positions
+                    .map(|(x, y, _)| {
+                        if (left..left + block_width).contains(&x)
+                            && (top..top + block_height).contains(&y)
+                        {
+                            *state
+                        } else {
+                            *background
+                        }
+                    })
+                    .collect()
+            }
+            Initializer::SoupInRect {
+                x: left,
+                y: top,
+                width: soup_width,
+                height: soup_height,
+                states,
+                background,
+            } => positions
+                .map(|(x, y, _)| {
+                    if (*left..left + soup_width).contains(&x)
+                        && (*top..top + soup_height).contains(&y)
+                    {
+                        draw(states, &mut rng)
+                    } else {
+                        Ok(*background)
+                    }
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Initializer::SoupInDisc {
+                x: disc_x,
+                y: disc_y,
+                radius,
+                states,
+                background,
+            } => positions
+                .map(|(x, y, _)| {
+                    let dx = x as f32 - *disc_x as f32;
+                    let dy = y as f32 - *disc_y as f32;
+                    if dx * dx + dy * dy <= radius * radius {
+                        draw(states, &mut rng)
+                    } else {
+                        Ok(*background)
+                    }
+                })
+                .collect::<anyhow::Result<_>>()?,
+            Initializer::Stripes {
+                axis,
+                width: stripe_width,
+                states,
+            } => {
+                anyhow::ensure!(
+                    *stripe_width > 0 && !states.is_empty(),
+                    "stripes need a width and at least one state"
+                );
+                positions
+                    .map(|(x, y, z)| {
+                        let along = match axis {
+                            Axis::X => x,
+                            Axis::Y => y,
+                            Axis::Z => z,
+                        };
+                        states[along / stripe_width % states.len()]
+                    })
+                    .collect()
+            }
+            Initializer::Checkerboard { size, states } => {
+                anyhow::ensure!(
+                    *size > 0,
+                    "checkerboard squares must be at least 1 cell wide"
+                );
+                positions
+                    .map(|(x, y, _)| states[(x / size + y / size) % 2])
+                    .collect()
+            }
+            Initializer::Noise {
+                correlation_length,
+                states,
+            } => self.noise(*correlation_length, states, &mut rng)?,
+        };
+
+        *self.cells_mut() = cells;
+        self.initialization = Some(Initialization { initializer, seed });
+
+        Ok(())
+    }
+
+    /// How the grid was laid out, if it was through [`Grid::initialize`] or
+    /// [`Grid::randomize`]
+    #[inline]
+    pub fn initialization(&self) -> Option<&Initialization> {
+        self.initialization.as_ref()
+    }
+
+    /// Smooths white noise over each slice, then hands out states by rank so
+    /// that each covers its share of the cells
+    fn noise(
+        &self,
+        correlation_length: f32,
+        states: &[StateProbabilty],
+        rng: &mut impl Rng,
+    ) -> anyhow::Result<Vec<NodeId>> {
+        let total_weight: f32 = states.iter().map(|sp| sp.weight).sum();
+        anyhow::ensure!(
+            total_weight > 0.0 && states.iter().all(|sp| sp.weight >= 0.0),
+            "noise needs non-negative weights, some of them positive"
+        );
+
+        let mut values: Vec<f32> = (0..self.n_cells).map(|_| rng.random()).collect();
+        let radius = (correlation_length / 2.0).round().max(0.0) as usize;
+        if radius > 0 {
+            let width = self.cells_per_row();
+            for slice in values.chunks_mut(self.neighbor_ctx.slice_len()) {
+                for _ in 0..NOISE_BLUR_PASSES {
+                    box_blur(slice, width, radius);
+                }
+            }
+        }
+
+        let mut ranks: Vec<usize> = (0..self.n_cells).collect();
+        ranks.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+        let thresholds: Vec<_> = states
+            .iter()
+            .scan(0.0, |covered, sp| {
+                *covered += sp.weight / total_weight;
+                Some((*covered, sp.state))
+            })
+            .collect();
+
+        let mut cells = vec![NodeId::default(); self.n_cells];
+        let mut current = 0;
+        for (rank, idx) in ranks.into_iter().enumerate() {
+            let share = (rank as f32 + 0.5) / self.n_cells as f32;
+            while current + 1 < thresholds.len() && share > thresholds[current].0 {
+                current += 1;
+            }
+            cells[idx] = thresholds[current].1;
+        }
+
+        Ok(cells)
+    }
+}
+
+fn draw(states: &[StateProbabilty], rng: &mut impl Rng) -> anyhow::Result<NodeId> {
+    Ok(states.choose_weighted(rng, |sp| sp.weight)?.state)
+}
+
+/// Replaces each value with the mean of those within `radius` along its row,
+/// then along its column, leaving out those past the edges
+fn box_blur(values: &mut [f32], width: usize, radius: usize) {
+    let height = values.len().div_ceil(width);
+    let mut blurred = values.to_vec();
+
+    let mean = |values: &[f32], at: &dyn Fn(usize) -> Option<usize>, center: usize, len: usize| {
+        let range = center.saturating_sub(radius)..(center + radius + 1).min(len);
+        let (sum, count) = range
+            .filter_map(at)
+            .fold((0.0, 0), |(sum, count), idx| (sum + values[idx], count + 1));
+        sum / count as f32
+    };
+
+    for (idx, value) in blurred.iter_mut().enumerate() {
+        let (x, y) = (idx % width, idx / width);
+        let in_row = |x: usize| Some(y * width + x).filter(|idx| *idx < values.len());
+        *value = mean(values, &in_row, x, width);
+    }
+    for (idx, value) in values.iter_mut().enumerate() {
+        let (x, y) = (idx % width, idx / width);
+        let in_column = |y: usize| Some(y * width + x).filter(|idx| *idx < blurred.len());
+        *value = mean(&blurred, &in_column, y, height);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{
+        neighbor_strategy::NeighboringStrategy,
+        test_utils::{game_of_life_grid, to_game_of_life_output},
+    };
+    use rstest::rstest;
+
+    const EMPTY: &str = "
+        ░░░░░░
+        ░░░░░░
+        ░░░░░░
+        ░░░░░░
+    ";
+
+    fn soup(weight: f32) -> Vec<StateProbabilty> {
+        vec![
+            StateProbabilty {
+                state: NodeId(0),
+                weight: 1.0 - weight,
+            },
+            StateProbabilty {
+                state: NodeId(1),
+                weight,
+            },
+        ]
+    }
+
+    #[rstest]
+    #[case(
+        Initializer::SingleCell { state: NodeId(1), background: NodeId(0) },
+        "░░░░░░\n░░░░░░\n░░░█░░\n░░░░░░"
+    )]
+    #[case(
+        Initializer::CentralBlock { width: 2, height: 3, state: NodeId(1), background: NodeId(0) },
+        "░░░░░░\n░░██░░\n░░██░░\n░░██░░"
+    )]
+    #[case(
+        Initializer::Stripes { axis: Axis::X, width: 2, states: vec![NodeId(1), NodeId(0)] },
+        "██░░██\n██░░██\n██░░██\n██░░██"
+    )]
+    #[case(
+        Initializer::Checkerboard { size: 2, states: [NodeId(0), NodeId(1)] },
+        "░░██░░\n░░██░░\n██░░██\n██░░██"
+    )]
+    #[case(
+        Initializer::SoupInRect { x: 1, y: 1, width: 3, height: 2, states: soup(1.0), background: NodeId(0) },
+        "░░░░░░\n░███░░\n░███░░\n░░░░░░"
+    )]
+    #[case(
+        Initializer::SoupInDisc { x: 2, y: 2, radius: 1.0, states: soup(1.0), background: NodeId(0) },
+        "░░░░░░\n░░█░░░\n░███░░\n░░█░░░"
+    )]
+    fn initializers_should_lay_out_cells(#[case] initializer: Initializer, #[case] expected: &str) {
+        let mut grid = game_of_life_grid(EMPTY);
+
+        grid.initialize(initializer, 0).unwrap();
+
+        assert_eq!(to_game_of_life_output(&grid), expected);
+    }
+
+    #[rstest]
+    #[case(Initializer::Random { states: soup(0.5) })]
+    #[case(Initializer::Noise { correlation_length: 4.0, states: soup(0.5) })]
+    fn seeded_initialization_should_be_reproducible(#[case] initializer: Initializer) {
+        let mut grid = Grid::empty(32 * 32, 32, NeighboringStrategy::SquareAndCorners);
+        grid.initialize(initializer.clone(), 7).unwrap();
+
+        let recorded = grid.initialization().unwrap().clone();
+        assert_eq!(recorded.seed, 7);
+
+        let mut replayed = Grid::empty(32 * 32, 32, NeighboringStrategy::SquareAndCorners);
+        replayed
+            .initialize(recorded.initializer, recorded.seed)
+            .unwrap();
+        assert_eq!(grid.cells(), replayed.cells());
+
+        replayed.initialize(initializer, 8).unwrap();
+        assert_ne!(grid.cells(), replayed.cells());
+    }
+
+    #[test]
+    fn edits_should_clear_the_initialization() {
+        let initializer = Initializer::Random { states: soup(0.5) };
+        let mut grid = game_of_life_grid(EMPTY);
+        let edits: [fn(&mut Grid); 4] = [
+            |grid| grid.set(0, 0, NodeId(1)).unwrap(),
+            |grid| grid.fill_rect(1, 1, 2, 2, NodeId(0)).unwrap(),
+            |grid| grid.paste(&game_of_life_grid("█"), 0, 0).unwrap(),
+            |grid| {
+                grid.flood_fill(0, 0, NodeId(2)).unwrap();
+            },
+        ];
+
+        for edit in edits {
+            grid.initialize(initializer.clone(), 3).unwrap();
+            grid.map_cells(|cell, _| cell);
+            assert!(grid.initialization().is_some());
+
+            edit(&mut grid);
+            assert_eq!(grid.initialization(), None);
+        }
+    }
+
+    #[test]
+    fn noise_should_form_patches_of_the_requested_share() {
+        // Pairs of side by side cells in the same state
+        let matching_pairs = |grid: &Grid| {
+            grid.cells()
+                .chunks(grid.cells_per_row())
+                .flat_map(|row| row.windows(2))
+                .filter(|pair| pair[0] == pair[1])
+                .count()
+        };
+        let mut white = Grid::empty(64 * 64, 64, NeighboringStrategy::SquareAndCorners);
+        let mut patchy = Grid::empty(64 * 64, 64, NeighboringStrategy::SquareAndCorners);
+
+        white
+            .initialize(
+                Initializer::Noise {
+                    correlation_length: 0.0,
+                    states: soup(0.25),
+                },
+                3,
+            )
+            .unwrap();
+        patchy
+            .initialize(
+                Initializer::Noise {
+                    correlation_length: 8.0,
+                    states: soup(0.25),
+                },
+                3,
+            )
+            .unwrap();
+
+        assert_eq!(patchy.population()[&NodeId(1)], 64 * 64 / 4);
+        assert!(matching_pairs(&patchy) > matching_pairs(&white) + 64 * 63 / 4);
+    }
+}
@@ -1,89 +1,633 @@
+mod bitpacked;
+mod edit;
+mod init;
 pub mod neighbor_strategy;
+pub mod sparse;
+mod transform;
 
 #[cfg(test)]
 pub mod test_utils;
 
-use neighbor_strategy::{IterNeighbors, NeighboringContext, NeighboringStrategy};
-use rand::seq::IndexedRandom;
-use rayon::iter::{
-    IndexedParallelIterator, IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator,
+use std::{collections::BTreeMap, sync::OnceLock};
+
+use anyhow::ensure;
+use bitpacked::BinaryRule;
+pub(crate) use bitpacked::BitGrid;
+pub use edit::GridError;
+pub use init::{Initialization, Initializer};
+use neighbor_strategy::{
+    Boundary, IterNeighbors, Neighbor, NeighboringContext, NeighboringStrategy,
+};
+use rayon::{
+    iter::{
+        IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+    },
+    slice::ParallelSliceMut,
 };
+use serde::{Deserialize, Serialize, Serializer};
+use strum::IntoStaticStr;
+pub use transform::{Anchor, Overflow, Rotation};
 
 use crate::{
-    model::NodeId,
-    state_map::{StateMap, StatePool},
+    model::{Block, Model, NodeId, BLOCK_SIZE},
+    state_map::StateMap,
     AVAILABLE_PARALLELISM,
 };
 
-#[derive(Debug)]
+/// How many row bands each worker gets, so that faster workers can pick up
+/// the slack of slower ones
+const BANDS_PER_WORKER: usize = 4;
+
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "SerializedGrid")]
 pub struct Grid {
     neighbor_ctx: NeighboringContext,
     n_cells: usize,
+    /// Out of date while the cells are held in `packed`
     cells: Vec<NodeId>,
+    /// Cells of two-state layers stepped by the bit-packed kernel, which
+    /// only get unpacked when they're read or edited
+    packed: Option<BitGrid>,
+    /// `packed` unpacked on the first read
+    unpacked: OnceLock<Vec<NodeId>>,
+    #[serde(skip)]
     next_cells: Vec<NodeId>,
+    /// Which cells changed in the last generation, or `None` when that's
+    /// unknown, such as on the first step or after an edit
+    #[serde(skip)]
+    changes: Option<Vec<bool>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    initialization: Option<Initialization>,
+}
+
+/// Layout grids are written in, borrowing the cells wherever they're held
+#[derive(Serialize)]
+#[serde(rename = "Grid")]
+struct SerializedGridRef<'g> {
+    neighbor_ctx: &'g NeighboringContext,
+    n_cells: usize,
+    cells: &'g [NodeId],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    initialization: Option<&'g Initialization>,
+}
+
+impl Serialize for Grid {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializedGridRef {
+            neighbor_ctx: &self.neighbor_ctx,
+            n_cells: self.n_cells,
+            cells: self.cells(),
+            initialization: self.initialization.as_ref(),
+        }
+        .serialize(serializer)
+    }
+}
+
+/// Layout grids are read from, checked by [`Grid::from_parts`] before they
+/// can be stepped
+#[derive(Deserialize)]
+struct SerializedGrid {
+    neighbor_ctx: NeighboringContext,
+    n_cells: usize,
+    cells: Vec<NodeId>,
+    #[serde(default)]
+    initialization: Option<Initialization>,
+}
+
+impl TryFrom<SerializedGrid> for Grid {
+    type Error = anyhow::Error;
+
+    fn try_from(grid: SerializedGrid) -> Result<Self, Self::Error> {
+        ensure!(
+            grid.n_cells == grid.cells.len(),
+            "grids of {} cells can't hold {} cells",
+            grid.n_cells,
+            grid.cells.len()
+        );
+
+        Self::from_parts(grid.neighbor_ctx, grid.cells, grid.initialization)
+    }
 }
 
 impl Grid {
     pub fn empty(n_cells: usize, cells_per_row: usize, strategy: NeighboringStrategy) -> Self {
         Self {
-            neighbor_ctx: NeighboringContext {
+            neighbor_ctx: NeighboringContext::new(
+                n_cells,
                 cells_per_row,
                 strategy,
-            },
+                Boundary::default(),
+            ),
             n_cells,
             cells: vec![Default::default(); n_cells],
+            packed: None,
+            unpacked: OnceLock::new(),
             next_cells: vec![Default::default(); n_cells],
+            changes: None,
+            initialization: None,
         }
     }
 
+    /// Empty grid spanning `extents` along the `x`, `y` and `z` axes and any
+    /// further ones, in that order. Missing `y` and `z` axes have a length
+    /// of 1.
+    ///
+    /// Cells are stored in `x`/`y` slices, stacked along `z` first, then
+    /// along each further axis in turn.
+    pub fn with_extents(extents: &[usize], strategy: NeighboringStrategy) -> anyhow::Result<Self> {
+        ensure!(!extents.is_empty(), "grids must have at least 1 dimension");
+        ensure!(
+            extents.iter().all(|len| *len > 0),
+            "every axis must be at least 1 cell long, got {extents:?}"
+        );
+
+        let n_cells = extents.iter().product();
+        let mut grid = Self::empty(n_cells, extents[0], strategy);
+        grid.neighbor_ctx.depth = extents.iter().skip(2).product();
+        grid.neighbor_ctx.hyper_extents = extents.iter().skip(3).copied().collect();
+
+        Ok(grid)
+    }
+
+    /// Grid laid out by `neighbor_ctx`, holding `cells`
+    pub(crate) fn from_parts(
+        neighbor_ctx: NeighboringContext,
+        cells: Vec<NodeId>,
+        initialization: Option<Initialization>,
+    ) -> anyhow::Result<Self> {
+        ensure!(
+            neighbor_ctx.cells_per_row > 0 && neighbor_ctx.depth > 0,
+            "grids must be at least 1 cell wide and 1 slice deep"
+        );
+        let hyper_len: usize = neighbor_ctx.hyper_extents.iter().product();
+        ensure!(
+            hyper_len > 0 && neighbor_ctx.depth.is_multiple_of(hyper_len),
+            "{} slices don't fit axes of {:?} past z",
+            neighbor_ctx.depth,
+            neighbor_ctx.hyper_extents
+        );
+        ensure!(
+            cells.len() == neighbor_ctx.n_cells
+                && neighbor_ctx.n_cells.is_multiple_of(neighbor_ctx.depth),
+            "{} cells don't fit a grid of {} cells over {} slices",
+            cells.len(),
+            neighbor_ctx.n_cells,
+            neighbor_ctx.depth
+        );
+
+        Ok(Self {
+            n_cells: cells.len(),
+            next_cells: vec![Default::default(); cells.len()],
+            neighbor_ctx,
+            cells,
+            packed: None,
+            unpacked: OnceLock::new(),
+            changes: None,
+            initialization,
+        })
+    }
+
+    /// Draws every cell from the weighted `state_probabilities`, with a fresh
+    /// seed recorded in [`Grid::initialization`]
     pub fn randomize(&mut self, state_probabilities: &[StateProbabilty]) -> anyhow::Result<()> {
-        self.cells.clear();
-        let mut rng = rand::rng();
+        let states = state_probabilities.to_vec();
+        self.initialize(Initializer::Random { states }, rand::random())
+    }
 
-        (0..self.cells.capacity())
-            .map(|_| state_probabilities.choose_weighted(&mut rng, |sp| sp.weight))
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(|sp| sp.state)
-            .collect_into(&mut self.cells);
+    pub fn map_cells<F>(&mut self, f: F)
+    where
+        F: Fn(NodeId, &StateMap) -> NodeId + Send + Sync,
+    {
+        let mut next_cells = self.take_next_cells();
+        // Maps grow to fit whichever states show up
+        self.compute_next(&mut next_cells, 0, false, |_, cell, state_map| {
+            f(cell, state_map)
+        });
+        self.commit_next(next_cells);
+    }
 
-        Ok(())
+    /// Like [`Grid::map_cells`], but only evaluates cells whose neighborhood,
+    /// themselves included, changed in the previous generation. The others
+    /// keep their state.
+    ///
+    /// This gives the same results as a full update as long as `f` is
+    /// deterministic and only looks at the cell and its neighbors. Every cell
+    /// is evaluated on the first step, or after the grid is edited.
+    pub fn map_active_cells<F>(&mut self, f: F)
+    where
+        F: Fn(NodeId, &StateMap) -> NodeId + Send + Sync,
+    {
+        let mut next_cells = self.take_next_cells();
+        self.compute_next(&mut next_cells, 0, true, |_, cell, state_map| {
+            f(cell, state_map)
+        });
+        self.commit_next(next_cells);
     }
 
-    pub fn map_cells<'s, F>(&mut self, state_pool: &'s StatePool, f: F)
+    /// Evaluates `f` for every cell, writing the results to `next_cells`
+    /// without touching the current generation.
+    ///
+    /// The grid is split into bands of whole rows, a few per worker, so that
+    /// every job walks contiguous memory and neighboring rows stay in cache.
+    /// Each worker counts neighbors in its own [`StateMap`], with room for
+    /// `n_states` states up front. With `skip_quiescent`, cells whose
+    /// neighborhood didn't change keep their state without calling `f`, see
+    /// [`Grid::map_active_cells`].
+    pub(crate) fn compute_next<F>(
+        &self,
+        next_cells: &mut [NodeId],
+        n_states: usize,
+        skip_quiescent: bool,
+        f: F,
+    ) where
+        F: Fn(usize, NodeId, &StateMap) -> NodeId + Send + Sync,
+    {
+        self.compute_next_tallied(
+            next_cells,
+            n_states,
+            |_| skip_quiescent,
+            0,
+            |idx, curr_state, state_map, _| f(idx, curr_state, state_map),
+        );
+    }
+
+    /// Like [`Grid::compute_next`], where `f` also adds to `n_tallies`
+    /// counters of its band, summed up once every band is done. Quiescent
+    /// cells are only skipped in states `skips_quiescent` accepts.
+    pub(crate) fn compute_next_tallied<S, F>(
+        &self,
+        next_cells: &mut [NodeId],
+        n_states: usize,
+        skips_quiescent: S,
+        n_tallies: usize,
+        f: F,
+    ) -> Vec<u64>
     where
-        F: Fn(NodeId, &'s StateMap) -> NodeId + Send + Sync,
+        S: Fn(NodeId) -> bool + Sync,
+        F: Fn(usize, NodeId, &StateMap, &mut [u64]) -> NodeId + Send + Sync,
     {
-        let chunk_size = *AVAILABLE_PARALLELISM;
-        self.cells
-            .chunks(chunk_size)
-            .zip(self.next_cells.chunks_mut(chunk_size))
+        let band_len = self.band_len();
+        let changes = self.changes.as_deref();
+        let cells = self.cells();
+
+        next_cells
+            .par_chunks_mut(band_len)
             .enumerate()
-            .for_each(|(outer_idx, (cells, next_cells))| {
-                cells
-                    .par_iter()
-                    .enumerate()
-                    .zip(next_cells.par_iter_mut())
-                    .for_each(|((inner_idx, cell), next_cell)| {
-                        let idx = outer_idx * chunk_size + inner_idx;
-                        let state_map = state_pool.get(idx);
-                        let neighbors = self.cells.iter_neighbors(idx, self.neighbor_ctx);
-                        state_map.count_states(neighbors);
-                        *next_cell = f(*cell, state_map);
-                    });
-            });
+            .map_init(
+                || StateMap::with_states(n_states),
+                |state_map, (band_idx, next_band)| {
+                    let mut tallies = vec![0; n_tallies];
+                    let start = band_idx * band_len;
+                    for (idx, next_cell) in (start..).zip(next_band.iter_mut()) {
+                        if let Some(changes) = changes {
+                            if skips_quiescent(cells[idx]) && !self.is_active(idx, changes) {
+                                *next_cell = cells[idx];
+                                continue;
+                            }
+                        }
+
+                        state_map.count_states(self.iter_neighbors(idx));
+                        *next_cell = f(idx, cells[idx], state_map, &mut tallies);
+                    }
 
+                    tallies
+                },
+            )
+            .reduce(
+                || vec![0; n_tallies],
+                |mut total, tallies| {
+                    total
+                        .iter_mut()
+                        .zip(tallies)
+                        .for_each(|(total, n)| *total += n);
+                    total
+                },
+            )
+    }
+
+    /// Whether the cell or any of its neighbors changed
+    #[inline]
+    fn is_active(&self, idx: usize, changes: &[bool]) -> bool {
+        changes[idx]
+            || self
+                .neighbor_ctx
+                .get_neighbors(idx)
+                .any(|neighbor| matches!(neighbor, Neighbor::Cell(idx) if changes[idx]))
+    }
+
+    /// Number of cells that stepping with change tracking would evaluate, or
+    /// `None` when they all would
+    pub fn n_active_cells(&self) -> Option<usize> {
+        let changes = self.changes.as_deref()?;
+        Some(
+            (0..self.n_cells)
+                .filter(|idx| self.is_active(*idx, changes))
+                .count(),
+        )
+    }
+
+    /// Drops the record of which cells changed, so that the next step
+    /// evaluates every cell
+    #[inline]
+    pub(crate) fn forget_changes(&mut self) {
+        self.changes = None;
+    }
+
+    /// Number of cells in each band of rows stepped by a single job
+    fn band_len(&self) -> usize {
+        let cells_per_row = self.cells_per_row().max(1);
+        let n_rows = self.n_cells.div_ceil(cells_per_row);
+        let rows_per_band = n_rows.div_ceil(*AVAILABLE_PARALLELISM * BANDS_PER_WORKER);
+
+        rows_per_band.max(1) * cells_per_row
+    }
+
+    /// Steps two-state outer-totalistic models 64 cells at a time, giving
+    /// the same generation `compute_next` would, still packed. Returns `None`
+    /// when the model and the grid don't fit the fast path at all.
+    ///
+    /// Cells stay packed from one step to the next, so they're only packed
+    /// again after being edited. With `firings`, the firings of each edge of
+    /// `model` are added to it.
+    pub(crate) fn next_bit_packed(
+        &self,
+        model: &Model,
+        firings: Option<&mut [u64]>,
+    ) -> Option<BitGrid> {
+        if !model.is_outer_totalistic() {
+            return None;
+        }
+        let n_neighbors = BitGrid::n_neighbors_of(self.neighbor_ctx.strategy())?;
+        let has_full_neighborhoods = self.boundary() != Boundary::Open;
+        let rule = BinaryRule::from_model(model, n_neighbors, has_full_neighborhoods)?;
+
+        match &self.packed {
+            Some(packed) => Some(packed.next(&rule, firings)),
+            None => Some(BitGrid::pack(self)?.next(&rule, firings)),
+        }
+    }
+
+    /// Makes the generation given by [`Grid::next_bit_packed`] the current one
+    pub(crate) fn commit_bit_packed(&mut self, packed: BitGrid) {
+        self.packed = Some(packed);
+        self.unpacked = OnceLock::new();
+        // Packed steps don't track which cells changed
+        self.changes = None;
+    }
+
+    /// Whether the cells are held bit-packed
+    #[inline]
+    pub fn is_bit_packed(&self) -> bool {
+        self.packed.is_some()
+    }
+
+    /// Takes the cells out of their packed form, if they're in one, so that
+    /// they can be written to
+    fn unpack(&mut self) {
+        if let Some(packed) = self.packed.take() {
+            self.cells = self.unpacked.take().unwrap_or_else(|| packed.unpack());
+        }
+    }
+
+    /// Cells to be edited, after which the whole grid is evaluated again and
+    /// no longer follows its [`Grid::initialization`]
+    fn cells_mut(&mut self) -> &mut Vec<NodeId> {
+        self.initialization = None;
+        self.stepped_cells_mut()
+    }
+
+    /// Cells moved on one by one within a step, which still started from
+    /// the grid's [`Grid::initialization`]
+    fn stepped_cells_mut(&mut self) -> &mut Vec<NodeId> {
+        self.unpack();
+        self.forget_changes();
+        &mut self.cells
+    }
+
+    /// Partitions the grid into 2×2 blocks, starting at `(offset, offset)`,
+    /// and rewrites each block with `f`. Blocks that would fall outside the
+    /// grid are copied over as they are.
+    pub(crate) fn compute_next_blocks<F>(&self, next_cells: &mut [NodeId], offset: usize, f: F)
+    where
+        F: Fn(&Block) -> Block + Send + Sync,
+    {
+        let cells = self.cells();
+        next_cells.copy_from_slice(cells);
+
+        let [width, height, depth] = self.neighbor_ctx.slice_extents();
+        // Blocks starting at the last row or column only fit when they can
+        // wrap around the boundary. Each slice is partitioned on its own.
+        let block_origins: Vec<_> = (0..depth)
+            .flat_map(|z| (offset..height).step_by(2).map(move |y| (y, z)))
+            .flat_map(|(y, z)| (offset..width).step_by(2).map(move |x| (x, y, z)))
+            .collect();
+
+        let blocks: Vec<_> = block_origins
+            .par_iter()
+            .filter_map(|&(x, y, z)| self.block_indexes(x, y, z))
+            .collect();
+
+        // Blocks wrapping around the boundary could land on cells another
+        // block already holds, in which case neither is rewritten
+        let mut n_claims = vec![0u8; cells.len()];
+        for idx in blocks.iter().flatten() {
+            n_claims[*idx] = n_claims[*idx].saturating_add(1);
+        }
+
+        let next_blocks: Vec<_> = blocks
+            .into_par_iter()
+            .filter(|idxs| idxs.iter().all(|idx| n_claims[*idx] == 1))
+            .map(|idxs| (idxs, f(&idxs.map(|idx| cells[idx]))))
+            .collect();
+
+        for (idxs, block) in next_blocks {
+            idxs.into_iter()
+                .zip(block)
+                .for_each(|(idx, state)| next_cells[idx] = state);
+        }
+    }
+
+    /// Indexes of the 2×2 block whose top-left corner is at `(x, y)` in the
+    /// slice `z`, if it fits within the grid as 4 distinct cells.
+    ///
+    /// Blocks only wrap around axes of even length, since the partition
+    /// would otherwise fold back onto its first block.
+    fn block_indexes(&self, x: usize, y: usize, z: usize) -> Option<[usize; BLOCK_SIZE]> {
+        let [width, height, _] = self.neighbor_ctx.slice_extents();
+        if (x + 1 == width && width % 2 == 1) || (y + 1 == height && height % 2 == 1) {
+            return None;
+        }
+
+        let (x, y) = (x as isize, y as isize);
+        let mut idxs = [0; BLOCK_SIZE];
+
+        for (idx, (dx, dy)) in idxs.iter_mut().zip([(0, 0), (1, 0), (0, 1), (1, 1)]) {
+            let Neighbor::Cell(block_idx) =
+                self.neighbor_ctx.resolve_in_slice_at(z, x + dx, y + dy)
+            else {
+                return None;
+            };
+            *idx = block_idx;
+        }
+
+        // Reflective boundaries mirror the edge cell onto itself
+        let is_distinct = (0..BLOCK_SIZE).all(|i| !idxs[i + 1..].contains(&idxs[i]));
+        is_distinct.then_some(idxs)
+    }
+
+    /// Hands out the scratch buffer for the next generation, which must be
+    /// given back through [`Grid::commit_next`]
+    pub(crate) fn take_next_cells(&mut self) -> Vec<NodeId> {
+        self.unpack();
+        let mut next_cells = std::mem::take(&mut self.next_cells);
+        // Deserialized grids don't carry a scratch buffer
+        next_cells.resize(self.cells.len(), Default::default());
+
+        next_cells
+    }
+
+    pub(crate) fn commit_next(&mut self, next_cells: Vec<NodeId>) {
+        self.unpack();
+        let mut changes = self.changes.take().unwrap_or_default();
+        changes.clear();
+        self.cells
+            .iter()
+            .zip(next_cells.iter())
+            .map(|(curr, next)| curr != next)
+            .collect_into(&mut changes);
+        self.changes = Some(changes);
+
+        self.next_cells = next_cells;
         std::mem::swap(&mut self.cells, &mut self.next_cells);
     }
 
+    /// Index of the cell `(dx, dy)` away from `idx`, through the boundary.
+    /// Virtual cells past the edges have no index.
+    pub(crate) fn offset_index(&self, idx: usize, dx: isize, dy: isize) -> Option<usize> {
+        match self.neighbor_ctx.offset(idx, dx, dy) {
+            Neighbor::Cell(offset_idx) => Some(offset_idx),
+            Neighbor::Virtual(_) | Neighbor::Missing => None,
+        }
+    }
+
+    /// How many cells are in each state. States with no cells are left out.
+    pub fn population(&self) -> BTreeMap<NodeId, usize> {
+        let mut population = BTreeMap::new();
+        self.cells()
+            .iter()
+            .for_each(|cell| *population.entry(*cell).or_default() += 1);
+
+        population
+    }
+
+    #[inline]
+    pub(crate) fn set_cell_at(&mut self, idx: usize, state: NodeId) {
+        self.cells_mut()[idx] = state;
+    }
+
+    /// Moves the cell at `idx` on to `state` within a step, unlike
+    /// [`Grid::set_cell_at`] which edits it
+    #[inline]
+    pub(crate) fn step_cell_at(&mut self, idx: usize, state: NodeId) {
+        self.stepped_cells_mut()[idx] = state;
+    }
+
+    #[inline]
+    pub fn iter_neighbors(&self, idx: usize) -> impl Iterator<Item = NodeId> + '_ {
+        self.cells().iter_neighbors(idx, &self.neighbor_ctx)
+    }
+
+    /// Whether both grids share the same lattice, so that cells with the
+    /// same index are in the same position
+    pub fn same_lattice(&self, other: &Grid) -> bool {
+        self.n_cells == other.n_cells && self.extents() == other.extents()
+    }
+
+    /// Copies out the plane perpendicular to `axis` at `position`, or `None`
+    /// when it lies past the end of the axis. On grids of more than 3
+    /// dimensions, `z` positions count slices along every axis past `y`.
+    pub fn slice(&self, axis: Axis, position: usize) -> Option<Slice> {
+        let [width, height, depth] = self.slice_extents();
+        let slice_len = self.neighbor_ctx.slice_len();
+
+        let (cells_per_row, cells) = match axis {
+            Axis::X if position < width => (
+                height,
+                (0..depth)
+                    .flat_map(|z| (0..height).map(move |y| z * slice_len + y * width + position))
+                    .collect::<Vec<_>>(),
+            ),
+            Axis::Y if position < height => (
+                width,
+                (0..depth)
+                    .flat_map(|z| (0..width).map(move |x| z * slice_len + position * width + x))
+                    .collect(),
+            ),
+            Axis::Z if position < depth => (
+                width,
+                (position * slice_len..(position + 1) * slice_len).collect(),
+            ),
+            _ => return None,
+        };
+
+        Some(Slice {
+            cells_per_row,
+            cells: cells
+                .into_iter()
+                .filter_map(|idx| self.cells().get(idx).copied())
+                .collect(),
+        })
+    }
+
     #[inline]
     pub fn n_cells(&self) -> usize {
         self.n_cells
     }
 
+    /// Rows in each slice
     #[inline]
     pub fn n_rows(&self) -> usize {
-        self.n_cells.div_ceil(self.neighbor_ctx.cells_per_row)
+        self.neighbor_ctx.n_rows()
+    }
+
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.neighbor_ctx.depth()
+    }
+
+    /// Length of the grid along each axis, starting with `x`, `y` and `z`
+    #[inline]
+    pub fn extents(&self) -> Vec<usize> {
+        self.neighbor_ctx.extents()
+    }
+
+    /// Width and height of each slice, then the number of slices
+    #[inline]
+    pub fn slice_extents(&self) -> [usize; 3] {
+        self.neighbor_ctx.slice_extents()
+    }
+
+    #[inline]
+    pub fn neighbor_ctx(&self) -> &NeighboringContext {
+        &self.neighbor_ctx
+    }
+
+    pub fn set_strategy(&mut self, strategy: NeighboringStrategy) {
+        self.unpack();
+        self.forget_changes();
+        self.neighbor_ctx.strategy = strategy;
+    }
+
+    #[inline]
+    pub fn boundary(&self) -> Boundary {
+        self.neighbor_ctx.boundary
+    }
+
+    pub fn set_boundary(&mut self, boundary: Boundary) {
+        // Ghost cells of packed grids stand for the old boundary
+        self.unpack();
+        self.forget_changes();
+        self.neighbor_ctx.boundary = boundary;
     }
 
     #[inline]
@@ -91,13 +635,240 @@ impl Grid {
         self.neighbor_ctx.cells_per_row
     }
 
+    /// Cells in index order, unpacked on first access after a bit-packed
+    /// step
     #[inline]
     pub fn cells(&self) -> &[NodeId] {
-        &self.cells
+        match &self.packed {
+            Some(packed) => self.unpacked.get_or_init(|| packed.unpack()),
+            None => &self.cells,
+        }
     }
 }
 
+/// Axis of a grid, used to pick the planes of volumetric grids
+#[derive(Clone, Copy, Debug, PartialEq, Eq, IntoStaticStr, Serialize, Deserialize)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// Plane of cells cut across a grid, laid out row by row
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Slice {
+    pub cells_per_row: usize,
+    pub cells: Vec<NodeId>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct StateProbabilty {
     pub state: NodeId,
     pub weight: f32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::test_utils::game_of_life_grid;
+    use rstest::rstest;
+
+    #[test]
+    fn grid_serialization() {
+        let mut grid = game_of_life_grid(
+            "
+            ░█
+            █░
+        ",
+        );
+        grid.set_boundary(Boundary::Toroidal);
+
+        insta::assert_ron_snapshot!(grid);
+    }
+
+    #[test]
+    fn deserialized_grid_should_step() {
+        let mut grid = game_of_life_grid(
+            "
+            ░░░
+            ███
+            ░░░
+        ",
+        );
+        grid.set_boundary(Boundary::Toroidal);
+
+        let serialized = ron::to_string(&grid).unwrap();
+        let deserialized: Grid = ron::from_str(&serialized).unwrap();
+
+        let model = crate::Model::game_of_life();
+        let mut ctx = crate::simulation::SimulationContext::new(model, deserialized);
+        ctx.step();
+
+        // Through the wrapped edges every dead cell sees three live ones, and
+        // every live cell sees two
+        assert_eq!(ctx.grid().boundary(), Boundary::Toroidal);
+        assert!(ctx.grid().cells().iter().all(|cell| *cell == NodeId(1)));
+    }
+
+    #[test]
+    fn deserialized_grids_should_be_validated() {
+        let grid = game_of_life_grid(
+            "
+            ░█
+            █░
+        ",
+        );
+        let serialized = ron::to_string(&grid).unwrap();
+
+        for (from, to) in [
+            ("n_cells:4,cells_per_row:2", "n_cells:4,cells_per_row:0"),
+            ("),n_cells:4", "),n_cells:5"),
+            ("cells:[(0),(1),(1),(0)]", "cells:[(0),(1),(1)]"),
+        ] {
+            let invalid = serialized.replace(from, to);
+            assert_ne!(invalid, serialized);
+            assert!(ron::from_str::<Grid>(&invalid).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn with_extents_should_reject_bad_dimensions() {
+        assert!(Grid::with_extents(&[], NeighboringStrategy::Square).is_err());
+        assert!(Grid::with_extents(&[2, 0, 2], NeighboringStrategy::Square).is_err());
+        assert!(Grid::with_extents(&[2, 2, 2, 0], NeighboringStrategy::Square).is_err());
+
+        let grid = Grid::with_extents(&[4, 3, 2], NeighboringStrategy::Square).unwrap();
+        assert_eq!(grid.extents(), [4, 3, 2]);
+        assert_eq!(grid.n_cells(), 24);
+    }
+
+    #[test]
+    fn with_extents_should_span_any_number_of_dimensions() {
+        let grid = Grid::with_extents(&[4, 3, 2, 5, 2], NeighboringStrategy::Square).unwrap();
+
+        assert_eq!(grid.extents(), [4, 3, 2, 5, 2]);
+        assert_eq!(grid.slice_extents(), [4, 3, 20]);
+        assert_eq!(grid.n_cells(), 240);
+        assert_eq!(grid.neighbor_ctx().position(239), [3, 2, 1, 4, 1]);
+
+        let serialized = ron::to_string(&grid).unwrap();
+        let deserialized: Grid = ron::from_str(&serialized).unwrap();
+        assert_eq!(deserialized.extents(), grid.extents());
+    }
+
+    #[rstest]
+    #[case(Axis::X, 1, 3, &[1, 4, 7, 10, 13, 16])]
+    #[case(Axis::Y, 2, 3, &[6, 7, 8, 15, 16, 17])]
+    #[case(Axis::Z, 1, 3, &[9, 10, 11, 12, 13, 14, 15, 16, 17])]
+    fn slice_should_cut_across_the_grid(
+        #[case] axis: Axis,
+        #[case] position: usize,
+        #[case] expected_cells_per_row: usize,
+        #[case] expected_cells: &[usize],
+    ) {
+        let mut grid = Grid::with_extents(&[3, 3, 2], NeighboringStrategy::Square).unwrap();
+        (0..grid.n_cells()).for_each(|idx| grid.set_cell_at(idx, NodeId(idx)));
+
+        let slice = grid.slice(axis, position).unwrap();
+
+        assert_eq!(slice.cells_per_row, expected_cells_per_row);
+        assert_eq!(
+            slice.cells,
+            expected_cells
+                .iter()
+                .map(|idx| NodeId(*idx))
+                .collect::<Vec<_>>()
+        );
+        assert!(grid.slice(axis, 3).is_none());
+    }
+
+    #[test]
+    fn volumetric_life_should_reach_across_slices() {
+        // A 3D Life variant where cells are born with exactly 4 neighbors
+        let mut grid =
+            Grid::with_extents(&[3, 3, 3], NeighboringStrategy::SquareAndCorners).unwrap();
+        [1, 3, 5, 7]
+            .into_iter()
+            .for_each(|idx| grid.set_cell_at(idx, NodeId(1)));
+
+        grid.map_cells(|_, state_map| NodeId((state_map.get_count(NodeId(1)) == 4) as usize));
+
+        // The centers of the first two slices see all four live cells
+        assert_eq!(grid.cells()[4], NodeId(1));
+        assert_eq!(grid.cells()[13], NodeId(1));
+        assert_eq!(grid.population()[&NodeId(1)], 2);
+    }
+
+    /// Cyclic model where a cell moves on to the next state once enough of
+    /// its neighbors are already there
+    fn cyclic_model(n_states: usize, threshold: u32) -> Model {
+        let mut model = Model::new();
+        (0..n_states).for_each(|state| model.add_node(crate::Node::new(format!("{state}"))));
+
+        for state in 0..n_states {
+            let next = NodeId((state + 1) % n_states);
+            let mut edge = crate::Edge::new(format!("{state} to next"), NodeId(state), next);
+            edge.add_condition(crate::Condition {
+                left: crate::Value::PopulationCount(next),
+                operand: crate::Operand::GreaterOrEqual,
+                right: crate::Value::Absolute(threshold),
+            });
+            model.add_edge(edge);
+        }
+
+        model
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::SquareAndCorners, Boundary::Toroidal, 3, 3)]
+    #[case(NeighboringStrategy::Moore { radius: 3 }, Boundary::Open, 5, 10)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 2 }, Boundary::Reflective, 4, 3)]
+    #[case(
+        NeighboringStrategy::Hexagon(Default::default()),
+        Boundary::Toroidal,
+        3,
+        2
+    )]
+    fn parallel_stepping_should_match_a_sequential_reference(
+        #[case] strategy: NeighboringStrategy,
+        #[case] boundary: Boundary,
+        #[case] n_states: usize,
+        #[case] threshold: u32,
+    ) {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        // Odd sizes, so that bands don't line up with anything in particular
+        let (width, height) = (151, 101);
+        let mut rng = StdRng::seed_from_u64(0xCA);
+        let mut grid = Grid::empty(width * height, width, strategy);
+        grid.set_boundary(boundary);
+        (0..grid.n_cells())
+            .for_each(|idx| grid.set_cell_at(idx, NodeId(rng.random_range(0..n_states))));
+
+        let model = cyclic_model(n_states, threshold);
+        let next_state = |cell, state_map: &StateMap| {
+            model.next_state(cell, &crate::layer::CellContext::new(0, state_map, &[]))
+        };
+
+        let mut reference = grid.cells().to_vec();
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(8)
+            .build()
+            .unwrap();
+
+        for _ in 0..5 {
+            let mut state_map = StateMap::new();
+            let next_reference: Vec<_> = (0..reference.len())
+                .map(|idx| {
+                    state_map.count_states(reference.iter_neighbors(idx, grid.neighbor_ctx()));
+                    next_state(reference[idx], &state_map)
+                })
+                .collect();
+            reference = next_reference;
+
+            pool.install(|| grid.map_cells(next_state));
+
+            assert_eq!(grid.cells(), reference.as_slice());
+        }
+    }
+}
@@ -1,81 +1,730 @@
-use std::ops::Rem;
+use serde::{Deserialize, Serialize};
+use strum::IntoStaticStr;
 
 use crate::model::NodeId;
 
 const MAX_NEIGHBORS_PER_CELL: usize = 8;
 
-#[derive(Debug, Clone, Copy)]
+/// Von Neumann neighborhood, as `(dx, dy)` offsets
+const SQUARE_OFFSETS: [(isize, isize); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Moore neighborhood, as `(dx, dy)` offsets
+const SQUARE_AND_CORNERS_OFFSETS: [(isize, isize); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (1, -1),
+    (-1, -1),
+    (0, 1),
+    (-1, 1),
+    (1, 1),
+];
+
+/// Hexagon neighbors in a row that isn't shoved to the right, as `(dx, dy)`
+/// offsets. Rows above and below it lean to the left.
+const HEXAGON_UNSHOVED_ROW_OFFSETS: [(isize, isize); 6] =
+    [(-1, 0), (1, 0), (-1, -1), (0, -1), (-1, 1), (0, 1)];
+
+/// Hexagon neighbors in a row shoved half a cell to the right, as `(dx, dy)`
+/// offsets. Rows above and below it lean to the right.
+const HEXAGON_SHOVED_ROW_OFFSETS: [(isize, isize); 6] =
+    [(-1, 0), (1, 0), (0, -1), (1, -1), (0, 1), (1, 1)];
+
+/// Triangles sharing an edge with an upward triangle, as `(dx, dy)` offsets
+const UPWARD_TRIANGLE_EDGE_OFFSETS: [(isize, isize); 3] = [(-1, 0), (1, 0), (0, 1)];
+
+/// Triangles sharing an edge with a downward triangle, as `(dx, dy)` offsets
+const DOWNWARD_TRIANGLE_EDGE_OFFSETS: [(isize, isize); 3] = [(-1, 0), (1, 0), (0, -1)];
+
+/// Triangles sharing a vertex with an upward triangle, as `(dx, dy)`
+/// offsets. The row under its base is wider than the one over its tip.
+const UPWARD_TRIANGLE_VERTEX_OFFSETS: [(isize, isize); 12] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-2, 0),
+    (-1, 0),
+    (1, 0),
+    (2, 0),
+    (-2, 1),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+    (2, 1),
+];
+
+/// Triangles sharing a vertex with a downward triangle, as `(dx, dy)`
+/// offsets. The row over its base is wider than the one under its tip.
+const DOWNWARD_TRIANGLE_VERTEX_OFFSETS: [(isize, isize); 12] = [
+    (-2, -1),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (2, -1),
+    (-2, 0),
+    (-1, 0),
+    (1, 0),
+    (2, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct NeighboringContext {
+    pub(super) n_cells: usize,
     pub(super) cells_per_row: usize,
     pub(super) strategy: NeighboringStrategy,
+    #[serde(default)]
+    pub(super) boundary: Boundary,
+    /// Number of `x`/`y` slices, stacked along every axis past `y`
+    #[serde(default = "single_slice", skip_serializing_if = "is_single_slice")]
+    pub(super) depth: usize,
+    /// Lengths of the axes past `z`, on grids of more than 3 dimensions.
+    /// Slices go along `z` first, then along each of these in turn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(super) hyper_extents: Vec<usize>,
+}
+
+fn single_slice() -> usize {
+    1
+}
+
+fn is_single_slice(depth: &usize) -> bool {
+    *depth == 1
 }
 
 impl NeighboringContext {
-    fn get_neighbors(&self, index: usize) -> IndexIter {
-        match self.strategy {
-            #[allow(unreachable_code)]
-            NeighboringStrategy::Square => IndexIter::new(&[
-                index.checked_sub(1),
-                index.checked_add(1),
-                index.checked_sub(self.cells_per_row),
-                index.checked_add(self.cells_per_row),
-                todo!("Evaluate edge cases"),
-            ]),
-            NeighboringStrategy::SquareAndCorners => {
-                let col = index.rem(self.cells_per_row);
-                let allow_left_side = col > 0;
-                let allow_right_side = col < self.cells_per_row - 1;
-
-                IndexIter::new(&[
-                    allow_left_side.then(|| index.checked_sub(1)).flatten(),
-                    allow_right_side.then(|| index.checked_add(1)).flatten(),
-                    index.checked_sub(self.cells_per_row),
-                    allow_right_side
-                        .then(|| index.checked_sub(self.cells_per_row - 1))
-                        .flatten(),
-                    allow_left_side
-                        .then(|| index.checked_sub(self.cells_per_row + 1))
-                        .flatten(),
-                    index.checked_add(self.cells_per_row),
-                    allow_left_side
-                        .then(|| index.checked_add(self.cells_per_row - 1))
-                        .flatten(),
-                    allow_right_side
-                        .then(|| index.checked_add(self.cells_per_row + 1))
-                        .flatten(),
-                ])
-            }
-            #[allow(unreachable_code)]
-            NeighboringStrategy::Hexagon => IndexIter::new(&[
-                index.checked_sub(1),
-                index.checked_add(1),
-                index.checked_sub(self.cells_per_row),
-                index.checked_sub(self.cells_per_row - 1),
-                index.checked_add(self.cells_per_row),
-                index.checked_add(self.cells_per_row - 1),
-                todo!("Evaluate edge cases"),
-            ]),
-        }
-    }
-}
-
-#[derive(Clone, Copy, Debug)]
+    pub fn new(
+        n_cells: usize,
+        cells_per_row: usize,
+        strategy: NeighboringStrategy,
+        boundary: Boundary,
+    ) -> Self {
+        Self {
+            n_cells,
+            cells_per_row,
+            strategy,
+            boundary,
+            depth: 1,
+            hyper_extents: Vec::new(),
+        }
+    }
+
+    /// Rows in each slice
+    #[inline]
+    pub fn n_rows(&self) -> usize {
+        self.slice_len().div_ceil(self.cells_per_row)
+    }
+
+    /// Number of `x`/`y` slices, which is 1 for planar grids
+    #[inline]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Lengths of the axes past `y`, starting with `z`
+    #[inline]
+    fn outer_extents(&self) -> impl Iterator<Item = usize> + '_ {
+        let hyper_len: usize = self.hyper_extents.iter().product();
+        std::iter::once(self.depth / hyper_len).chain(self.hyper_extents.iter().copied())
+    }
+
+    #[inline]
+    pub fn n_cells(&self) -> usize {
+        self.n_cells
+    }
+
+    /// Number of cells in each slice
+    #[inline]
+    pub fn slice_len(&self) -> usize {
+        self.n_cells / self.depth
+    }
+
+    #[inline]
+    pub fn is_volumetric(&self) -> bool {
+        self.depth > 1
+    }
+
+    /// Length of the grid along each axis, starting with `x`, `y` and `z`
+    pub fn extents(&self) -> Vec<usize> {
+        [self.cells_per_row, self.n_rows()]
+            .into_iter()
+            .chain(self.outer_extents())
+            .collect()
+    }
+
+    /// Width and height of each slice, then the number of slices, which is
+    /// the depth of 3 dimensional grids
+    #[inline]
+    pub fn slice_extents(&self) -> [usize; 3] {
+        [self.cells_per_row, self.n_rows(), self.depth]
+    }
+
+    /// Coordinates of the cell at `index` along each axis, in the order of
+    /// [`NeighboringContext::extents`]
+    pub fn position(&self, index: usize) -> Vec<usize> {
+        let (x, y, mut slice) = self.coords(index);
+        let mut position = vec![x, y];
+        for len in self.outer_extents() {
+            position.push(slice % len);
+            slice /= len;
+        }
+
+        position
+    }
+
+    /// Coordinates of the cell at `index`, as `(x, y, z)`, where `z` is the
+    /// slice it lies in
+    #[inline]
+    pub fn coords(&self, index: usize) -> (usize, usize, usize) {
+        let slice_len = self.slice_len();
+        let in_slice = index % slice_len;
+
+        (
+            in_slice % self.cells_per_row,
+            in_slice / self.cells_per_row,
+            index / slice_len,
+        )
+    }
+
+    #[inline]
+    pub fn strategy(&self) -> &NeighboringStrategy {
+        &self.strategy
+    }
+
+    #[inline]
+    pub fn boundary(&self) -> Boundary {
+        self.boundary
+    }
+
+    pub(crate) fn get_neighbors(&self, index: usize) -> NeighborIter<'_> {
+        let offsets = match &self.strategy {
+            NeighboringStrategy::Square | NeighboringStrategy::VonNeumann { radius: 1 }
+                if !self.is_volumetric() =>
+            {
+                return self.fixed_neighbors(index, SQUARE_OFFSETS);
+            }
+            NeighboringStrategy::SquareAndCorners | NeighboringStrategy::Moore { radius: 1 }
+                if !self.is_volumetric() =>
+            {
+                return self.fixed_neighbors(index, SQUARE_AND_CORNERS_OFFSETS);
+            }
+            NeighboringStrategy::Square => OffsetIter::Radius(self.radius_iter(1, true)),
+            NeighboringStrategy::SquareAndCorners => OffsetIter::Radius(self.radius_iter(1, false)),
+            NeighboringStrategy::Hexagon(layout) => {
+                let (_, y, _) = self.coords(index);
+                let offsets = if layout.is_shoved(y) {
+                    HEXAGON_SHOVED_ROW_OFFSETS
+                } else {
+                    HEXAGON_UNSHOVED_ROW_OFFSETS
+                };
+
+                return self.fixed_neighbors(index, offsets);
+            }
+            NeighboringStrategy::Triangle(adjacency) => {
+                let (x, y, _) = self.coords(index);
+
+                match (adjacency, is_upward_triangle(x, y)) {
+                    (TriangleAdjacency::Edges, true) => {
+                        return self.fixed_neighbors(index, UPWARD_TRIANGLE_EDGE_OFFSETS);
+                    }
+                    (TriangleAdjacency::Edges, false) => {
+                        return self.fixed_neighbors(index, DOWNWARD_TRIANGLE_EDGE_OFFSETS);
+                    }
+                    // Too many to fit the fixed buffer, but still static
+                    (TriangleAdjacency::Vertices, true) => {
+                        OffsetIter::Mask(UPWARD_TRIANGLE_VERTEX_OFFSETS.iter())
+                    }
+                    (TriangleAdjacency::Vertices, false) => {
+                        OffsetIter::Mask(DOWNWARD_TRIANGLE_VERTEX_OFFSETS.iter())
+                    }
+                }
+            }
+            NeighboringStrategy::Moore { radius } => {
+                OffsetIter::Radius(self.radius_iter(*radius, false))
+            }
+            NeighboringStrategy::VonNeumann { radius } => {
+                OffsetIter::Radius(self.radius_iter(*radius, true))
+            }
+            NeighboringStrategy::Custom(offsets) => OffsetIter::Mask(offsets.iter()),
+        };
+
+        NeighborIter::Lazy {
+            ctx: self,
+            index,
+            offsets,
+        }
+    }
+
+    /// Radius neighborhoods only reach into other slices on volumetric
+    /// grids, where they span every axis past `y`
+    #[inline]
+    fn radius_iter(&self, radius: usize, is_diamond: bool) -> RadiusIter {
+        let n_outer_axes = if self.is_volumetric() {
+            1 + self.hyper_extents.len()
+        } else {
+            0
+        };
+
+        RadiusIter::new(radius, n_outer_axes, is_diamond)
+    }
+
+    #[inline]
+    fn fixed_neighbors<const N: usize>(
+        &self,
+        index: usize,
+        offsets: [(isize, isize); N],
+    ) -> NeighborIter<'_> {
+        NeighborIter::Fixed(IndexIter::new(
+            &offsets.map(|(dx, dy)| self.offset(index, dx, dy)),
+        ))
+    }
+
+    /// Resolves the cell `(dx, dy)` away from `index` within its slice,
+    /// going through the boundary when it falls outside the grid
+    #[inline]
+    pub fn offset(&self, index: usize, dx: isize, dy: isize) -> Neighbor {
+        self.offset_outer(index, dx, dy, OuterOffset::NONE)
+    }
+
+    /// Resolves the cell `(dx, dy)` away from `index`, in the slice `outer`
+    /// away along the axes past `y`. Axes 1 cell long have nothing past
+    /// them whatever the boundary.
+    fn offset_outer(&self, index: usize, dx: isize, dy: isize, outer: OuterOffset) -> Neighbor {
+        let (x, y, slice) = self.coords(index);
+
+        let mut rest = slice;
+        let mut stride = 1;
+        let mut target = 0;
+        for (axis, len) in self.outer_extents().enumerate() {
+            let coord = rest % len;
+            rest /= len;
+            let coord = match outer.along(axis) {
+                0 => coord,
+                _ if len == 1 => return Neighbor::Missing,
+                delta => match self.resolve_outer(coord as isize + delta, len) {
+                    Ok(coord) => coord,
+                    Err(neighbor) => return neighbor,
+                },
+            };
+            target += coord * stride;
+            stride *= len;
+        }
+
+        self.resolve_in_slice_at(target, x as isize + dx, y as isize + dy)
+    }
+
+    /// Maps possibly out-of-bounds coordinates along each axis, in the order
+    /// of [`NeighboringContext::extents`], to a cell of the grid. Missing
+    /// trailing coordinates are 0.
+    ///
+    /// Toroidal, reflective and fixed boundaries apply to the axes past `y`
+    /// as well, while the surfaces glued along `x` leave them open.
+    pub fn resolve(&self, position: &[isize]) -> Neighbor {
+        let mut stride = 1;
+        let mut slice = 0;
+        for (axis, len) in self.outer_extents().enumerate() {
+            let coord = position.get(axis + 2).copied().unwrap_or_default();
+            match self.resolve_outer(coord, len) {
+                Ok(coord) => slice += coord * stride,
+                Err(neighbor) => return neighbor,
+            }
+            stride *= len;
+        }
+
+        let x = position.first().copied().unwrap_or_default();
+        let y = position.get(1).copied().unwrap_or_default();
+        self.resolve_in_slice_at(slice, x, y)
+    }
+
+    /// Maps a coordinate along an axis past `y`, `len` cells long, through
+    /// the boundary
+    fn resolve_outer(&self, coord: isize, len: usize) -> Result<usize, Neighbor> {
+        let len = len as isize;
+        let coord = match self.boundary {
+            _ if (0..len).contains(&coord) => coord,
+            Boundary::Fixed(state) => return Err(Neighbor::Virtual(state)),
+            Boundary::Toroidal => coord.rem_euclid(len),
+            Boundary::Reflective => reflect(coord, len),
+            Boundary::Open | Boundary::Cylinder | Boundary::Mobius | Boundary::KleinBottle => {
+                return Err(Neighbor::Missing);
+            }
+        };
+
+        Ok(coord as usize)
+    }
+
+    /// Resolves `(x, y)` within the slice at `slice`
+    #[inline]
+    pub(super) fn resolve_in_slice_at(&self, slice: usize, x: isize, y: isize) -> Neighbor {
+        match self.resolve_in_slice(x, y) {
+            Neighbor::Cell(index) => Neighbor::Cell(slice * self.slice_len() + index),
+            neighbor => neighbor,
+        }
+    }
+
+    fn resolve_in_slice(&self, x: isize, y: isize) -> Neighbor {
+        let width = self.cells_per_row as isize;
+        let height = self.n_rows() as isize;
+
+        let is_inside = |x: isize, y: isize| (0..width).contains(&x) && (0..height).contains(&y);
+        let flip = |y: isize| height - 1 - y;
+
+        let (x, y) = match self.boundary {
+            _ if is_inside(x, y) => (x, y),
+            Boundary::Open => return Neighbor::Missing,
+            Boundary::Fixed(state) => return Neighbor::Virtual(state),
+            Boundary::Toroidal => (x.rem_euclid(width), y.rem_euclid(height)),
+            Boundary::Reflective => (reflect(x, width), reflect(y, height)),
+            Boundary::Cylinder => (x.rem_euclid(width), y),
+            Boundary::Mobius => {
+                // Crossing the seam an odd number of times flips the strip
+                let y = if x.div_euclid(width) % 2 == 0 {
+                    y
+                } else {
+                    flip(y)
+                };
+                (x.rem_euclid(width), y)
+            }
+            Boundary::KleinBottle => {
+                let y = y.rem_euclid(height);
+                let y = if x.div_euclid(width) % 2 == 0 {
+                    y
+                } else {
+                    flip(y)
+                };
+                (x.rem_euclid(width), y)
+            }
+        };
+
+        if !is_inside(x, y) {
+            return Neighbor::Missing;
+        }
+
+        let index = (y * width + x) as usize;
+        if index < self.slice_len() {
+            Neighbor::Cell(index)
+        } else {
+            Neighbor::Missing
+        }
+    }
+}
+
+/// Mirrors a coordinate back into `0..len`, repeating the edge cell
+fn reflect(coord: isize, len: isize) -> isize {
+    let period = 2 * len;
+    let coord = coord.rem_euclid(period);
+
+    if coord < len {
+        coord
+    } else {
+        period - 1 - coord
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
 pub enum NeighboringStrategy {
+    /// Von Neumann neighborhood: the 4 orthogonally adjacent cells, or 6 on
+    /// volumetric grids
     Square,
+    /// Moore neighborhood: the 8 surrounding cells, or 26 on volumetric grids
     SquareAndCorners,
-    Hexagon,
+    /// Pointy-top hexagons stored in offset rows. Like the other tilings,
+    /// it stays within each slice of volumetric grids.
+    Hexagon(HexLayout),
+    /// Triangles alternating between pointing up and down, see
+    /// [`is_upward_triangle`]. Toroidal grids need an even number of rows
+    /// and columns to keep the alternation across the seams.
+    Triangle(TriangleAdjacency),
+    /// Every cell within a square reaching `radius` cells away, or a cube
+    /// on volumetric grids
+    Moore { radius: usize },
+    /// Every cell at most `radius` orthogonal steps away, on every axis of
+    /// the grid
+    VonNeumann { radius: usize },
+    /// Arbitrary `(dx, dy)` offsets, such as a custom kernel
+    Custom(Vec<(isize, isize)>),
+}
+
+impl NeighboringStrategy {
+    /// Farthest any neighbor lies along either axis
+    pub fn reach(&self) -> usize {
+        match self {
+            NeighboringStrategy::Square
+            | NeighboringStrategy::SquareAndCorners
+            | NeighboringStrategy::Hexagon(_)
+            | NeighboringStrategy::Triangle(TriangleAdjacency::Edges) => 1,
+            NeighboringStrategy::Triangle(TriangleAdjacency::Vertices) => 2,
+            NeighboringStrategy::Moore { radius } | NeighboringStrategy::VonNeumann { radius } => {
+                *radius
+            }
+            NeighboringStrategy::Custom(offsets) => offsets
+                .iter()
+                .map(|(dx, dy)| dx.unsigned_abs().max(dy.unsigned_abs()))
+                .max()
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Offsets of the neighbors of the cell at `(x, y)` on an unbounded
+    /// plane, where coordinates may well be negative
+    pub(crate) fn planar_offsets(&self, x: isize, y: isize) -> OffsetIter<'_> {
+        let (x, y) = (x.rem_euclid(2) as usize, y.rem_euclid(2) as usize);
+
+        match self {
+            NeighboringStrategy::Square => OffsetIter::Mask(SQUARE_OFFSETS.iter()),
+            NeighboringStrategy::SquareAndCorners => {
+                OffsetIter::Mask(SQUARE_AND_CORNERS_OFFSETS.iter())
+            }
+            NeighboringStrategy::Hexagon(layout) if layout.is_shoved(y) => {
+                OffsetIter::Mask(HEXAGON_SHOVED_ROW_OFFSETS.iter())
+            }
+            NeighboringStrategy::Hexagon(_) => {
+                OffsetIter::Mask(HEXAGON_UNSHOVED_ROW_OFFSETS.iter())
+            }
+            NeighboringStrategy::Triangle(adjacency) => {
+                let offsets: &[_] = match (adjacency, is_upward_triangle(x, y)) {
+                    (TriangleAdjacency::Edges, true) => &UPWARD_TRIANGLE_EDGE_OFFSETS,
+                    (TriangleAdjacency::Edges, false) => &DOWNWARD_TRIANGLE_EDGE_OFFSETS,
+                    (TriangleAdjacency::Vertices, true) => &UPWARD_TRIANGLE_VERTEX_OFFSETS,
+                    (TriangleAdjacency::Vertices, false) => &DOWNWARD_TRIANGLE_VERTEX_OFFSETS,
+                };
+
+                OffsetIter::Mask(offsets.iter())
+            }
+            NeighboringStrategy::Moore { radius } => {
+                OffsetIter::Radius(RadiusIter::new(*radius, 0, false))
+            }
+            NeighboringStrategy::VonNeumann { radius } => {
+                OffsetIter::Radius(RadiusIter::new(*radius, 0, true))
+            }
+            NeighboringStrategy::Custom(offsets) => OffsetIter::Mask(offsets.iter()),
+        }
+    }
+}
+
+/// Which rows of a hexagonal grid are shoved half a cell to the right
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum HexLayout {
+    /// "odd-r" layout
+    #[default]
+    OddRows,
+    /// "even-r" layout
+    EvenRows,
+}
+
+impl HexLayout {
+    #[inline]
+    fn is_shoved(self, row: usize) -> bool {
+        match self {
+            HexLayout::OddRows => !row.is_multiple_of(2),
+            HexLayout::EvenRows => row.is_multiple_of(2),
+        }
+    }
+}
+
+/// Which triangles count as neighbors
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum TriangleAdjacency {
+    /// The 3 triangles sharing an edge
+    #[default]
+    Edges,
+    /// The 12 triangles sharing at least a vertex
+    Vertices,
+}
+
+/// Whether the triangle at `(x, y)` points up. The top-left triangle does,
+/// and they alternate from there in both directions.
+#[inline]
+pub fn is_upward_triangle(x: usize, y: usize) -> bool {
+    (x + y).is_multiple_of(2)
+}
+
+/// What lies past the edges of the grid
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default, IntoStaticStr)]
+pub enum Boundary {
+    /// Nothing: cells at the edges simply have fewer neighbors
+    #[default]
+    Open,
+    /// Opposite edges are glued together
+    Toroidal,
+    /// Edges act as mirrors
+    Reflective,
+    /// Every cell past the edges is in the given state
+    Fixed(NodeId),
+    /// Left and right edges are glued together
+    Cylinder,
+    /// Left and right edges are glued together, upside down
+    #[strum(serialize = "Möbius")]
+    Mobius,
+    /// Left and right edges are glued together upside down, while top and
+    /// bottom are glued as they are
+    #[strum(serialize = "Klein bottle")]
+    KleinBottle,
+}
+
+/// A neighbor as seen through the grid's [`Boundary`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Neighbor {
+    Cell(usize),
+    /// Virtual cell past the edges in a fixed state
+    Virtual(NodeId),
+    Missing,
+}
+
+/// Neighbors of a single cell
+pub(crate) enum NeighborIter<'c> {
+    /// Small neighborhoods are resolved up front, on the stack
+    Fixed(IndexIter),
+    /// Larger ones are resolved one offset at a time
+    Lazy {
+        ctx: &'c NeighboringContext,
+        index: usize,
+        offsets: OffsetIter<'c>,
+    },
+}
+
+impl Iterator for NeighborIter<'_> {
+    type Item = Neighbor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            NeighborIter::Fixed(index_iter) => index_iter.next(),
+            NeighborIter::Lazy {
+                ctx,
+                index,
+                offsets,
+            } => offsets
+                .map(|(dx, dy, outer)| ctx.offset_outer(*index, dx, dy, outer))
+                .find(|neighbor| *neighbor != Neighbor::Missing),
+        }
+    }
+}
+
+pub(crate) enum OffsetIter<'c> {
+    Radius(RadiusIter),
+    Mask(std::slice::Iter<'c, (isize, isize)>),
+}
+
+impl Iterator for OffsetIter<'_> {
+    type Item = (isize, isize, OuterOffset);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            OffsetIter::Radius(radius_iter) => radius_iter.next(),
+            OffsetIter::Mask(mask_iter) => mask_iter
+                .next()
+                .map(|&(dx, dy)| (dx, dy, OuterOffset::NONE)),
+        }
+    }
+}
+
+/// Offset along each axis past `y`, packed as the digits of a number in
+/// base `2 * radius + 1`, so that any number of axes fit in a word
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct OuterOffset {
+    code: usize,
+    radius: usize,
+    n_axes: usize,
 }
 
-struct IndexIter {
+impl OuterOffset {
+    /// Staying in the same slice
+    const NONE: Self = Self {
+        code: 0,
+        radius: 0,
+        n_axes: 0,
+    };
+
+    /// Offset along the `axis`th axis past `y`
+    #[inline]
+    fn along(self, axis: usize) -> isize {
+        if axis >= self.n_axes {
+            return 0;
+        }
+        let base = 2 * self.radius + 1;
+        (self.code / base.pow(axis as u32) % base) as isize - self.radius as isize
+    }
+}
+
+/// Goes through the box of offsets within `radius`, slice by slice in
+/// reading order, skipping the center cell
+pub(crate) struct RadiusIter {
+    radius: isize,
+    /// Axes past `y` the box reaches along, which is 0 for planar grids
+    n_outer_axes: usize,
+    dx: isize,
+    dy: isize,
+    /// Offset along the axes past `y`, see [`OuterOffset`]
+    outer_code: usize,
+    /// Number of offsets along the axes past `y`
+    n_outer_codes: usize,
+    /// Only keep offsets within `radius` orthogonal steps
+    is_diamond: bool,
+}
+
+impl RadiusIter {
+    fn new(radius: usize, n_outer_axes: usize, is_diamond: bool) -> Self {
+        Self {
+            n_outer_axes,
+            dx: -(radius as isize),
+            dy: -(radius as isize),
+            outer_code: 0,
+            n_outer_codes: (2 * radius + 1).pow(n_outer_axes as u32),
+            radius: radius as isize,
+            is_diamond,
+        }
+    }
+}
+
+impl Iterator for RadiusIter {
+    type Item = (isize, isize, OuterOffset);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.outer_code < self.n_outer_codes {
+            let outer = OuterOffset {
+                code: self.outer_code,
+                radius: self.radius as usize,
+                n_axes: self.n_outer_axes,
+            };
+            let offset = (self.dx, self.dy, outer);
+
+            self.dx += 1;
+            if self.dx > self.radius {
+                self.dx = -self.radius;
+                self.dy += 1;
+            }
+            if self.dy > self.radius {
+                self.dy = -self.radius;
+                self.outer_code += 1;
+            }
+
+            let outer_distances = (0..self.n_outer_axes).map(|axis| outer.along(axis).abs());
+            let distance = offset.0.abs() + offset.1.abs() + outer_distances.sum::<isize>();
+            let is_center = distance == 0;
+            let is_out_of_diamond = self.is_diamond && distance > self.radius;
+
+            if !is_center && !is_out_of_diamond {
+                return Some(offset);
+            }
+        }
+
+        None
+    }
+}
+
+pub(crate) struct IndexIter {
     curr: usize,
-    indexes: [Option<usize>; MAX_NEIGHBORS_PER_CELL],
+    indexes: [Neighbor; MAX_NEIGHBORS_PER_CELL],
 }
 
 impl IndexIter {
-    pub fn new(list: &[Option<usize>]) -> Self {
-        let mut indexes = [None; MAX_NEIGHBORS_PER_CELL];
+    pub fn new(list: &[Neighbor]) -> Self {
+        let mut indexes = [Neighbor::Missing; MAX_NEIGHBORS_PER_CELL];
         list.iter()
-            .filter(|l| l.is_some())
+            .filter(|l| **l != Neighbor::Missing)
             .zip(indexes.iter_mut())
             .for_each(|(l, n)| *n = *l);
 
@@ -84,12 +733,12 @@ impl IndexIter {
 }
 
 impl Iterator for IndexIter {
-    type Item = usize;
+    type Item = Neighbor;
 
     fn next(&mut self) -> Option<Self::Item> {
         let element = self.indexes.get(self.curr)?;
         self.curr += 1;
-        *element
+        (*element != Neighbor::Missing).then_some(*element)
     }
 }
 
@@ -97,21 +746,22 @@ pub trait IterNeighbors {
     fn iter_neighbors(
         &self,
         idx: usize,
-        strategy: NeighboringContext,
+        strategy: &NeighboringContext,
     ) -> impl Iterator<Item = NodeId>;
 }
 
-impl IterNeighbors for Vec<NodeId> {
+impl IterNeighbors for [NodeId] {
     fn iter_neighbors(
         &self,
         idx: usize,
-        n_ctx: NeighboringContext,
+        n_ctx: &NeighboringContext,
     ) -> impl Iterator<Item = NodeId> {
-        let neighbor_idxs = n_ctx.get_neighbors(idx);
-        neighbor_idxs
-            .into_iter()
-            .flat_map(|idx| self.get(idx))
-            .copied()
+        let neighbors = n_ctx.get_neighbors(idx);
+        neighbors.into_iter().flat_map(|neighbor| match neighbor {
+            Neighbor::Cell(idx) => self.get(idx).copied(),
+            Neighbor::Virtual(state) => Some(state),
+            Neighbor::Missing => None,
+        })
     }
 }
 
@@ -120,27 +770,51 @@ mod tests {
     use super::*;
     use rstest::rstest;
 
+    fn cell_indexes(neighbors: impl Iterator<Item = Neighbor>) -> Vec<usize> {
+        let mut indexes: Vec<_> = neighbors
+            .filter_map(|neighbor| match neighbor {
+                Neighbor::Cell(idx) => Some(idx),
+                _ => None,
+            })
+            .collect();
+        indexes.sort();
+
+        indexes
+    }
+
     #[rstest]
     #[case(
         NeighboringContext{
+            n_cells: 16,
             cells_per_row: 4,
             strategy: NeighboringStrategy::SquareAndCorners,
+            boundary: Boundary::Open,
+            depth: 1,
+            hyper_extents: Vec::new(),
         },
         5,
         &[0, 1, 2, 4, 6, 8, 9, 10]
     )]
     #[case(
         NeighboringContext{
+            n_cells: 12,
             cells_per_row: 3,
             strategy: NeighboringStrategy::SquareAndCorners,
+            boundary: Boundary::Open,
+            depth: 1,
+            hyper_extents: Vec::new(),
         },
         6,
         &[3, 4, 7, 9, 10]
     )]
     #[case(
         NeighboringContext{
+            n_cells: 12,
             cells_per_row: 3,
             strategy: NeighboringStrategy::SquareAndCorners,
+            boundary: Boundary::Open,
+            depth: 1,
+            hyper_extents: Vec::new(),
         },
         8,
         &[4, 5, 7, 10, 11]
@@ -150,9 +824,215 @@ mod tests {
         #[case] idx: usize,
         #[case] expected_indexes: &[usize],
     ) {
-        let mut actual_indexes: Vec<_> = neighbor_ctx.get_neighbors(idx).collect();
-        actual_indexes.sort();
+        let actual_indexes = cell_indexes(neighbor_ctx.get_neighbors(idx));
+
+        assert_eq!(actual_indexes, expected_indexes);
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::Square, 0, &[1, 4])]
+    #[case(NeighboringStrategy::Square, 3, &[2, 7])]
+    #[case(NeighboringStrategy::Square, 5, &[1, 4, 6, 9])]
+    #[case(NeighboringStrategy::Square, 7, &[3, 6, 11])]
+    #[case(NeighboringStrategy::Square, 12, &[8, 13])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 0, &[1, 4])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 4, &[0, 1, 5, 8, 9])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 5, &[1, 2, 4, 6, 9, 10])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 7, &[3, 6, 11])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 15, &[11, 14])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::EvenRows), 0, &[1, 4, 5])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::EvenRows), 3, &[2, 7])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::EvenRows), 4, &[0, 5, 8])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::EvenRows), 6, &[1, 2, 5, 7, 9, 10])]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::EvenRows), 12, &[8, 13])]
+    fn corner_and_edge_neighbors_return_expected_results(
+        #[case] strategy: NeighboringStrategy,
+        #[case] idx: usize,
+        #[case] expected_indexes: &[usize],
+    ) {
+        let neighbor_ctx = NeighboringContext::new(16, 4, strategy, Boundary::Open);
+
+        let actual_indexes = cell_indexes(neighbor_ctx.get_neighbors(idx));
+
+        assert_eq!(actual_indexes, expected_indexes);
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::Moore { radius: 2 }, 12, 24)]
+    #[case(NeighboringStrategy::Moore { radius: 2 }, 0, 8)]
+    #[case(NeighboringStrategy::Moore { radius: 3 }, 12, 24)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 2 }, 12, 12)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 2 }, 0, 5)]
+    #[case(NeighboringStrategy::Custom(vec![(0, -2), (0, 2), (-2, 0), (2, 0)]), 12, 4)]
+    #[case(NeighboringStrategy::Custom(vec![(0, -2), (0, 2), (-2, 0), (2, 0)]), 2, 3)]
+    fn extended_neighborhoods_return_expected_counts(
+        #[case] strategy: NeighboringStrategy,
+        #[case] idx: usize,
+        #[case] expected_count: usize,
+    ) {
+        let neighbor_ctx = NeighboringContext::new(25, 5, strategy, Boundary::Open);
+
+        assert_eq!(neighbor_ctx.get_neighbors(idx).count(), expected_count);
+    }
+
+    #[rstest]
+    #[case(TriangleAdjacency::Edges, 0, &[1, 5])]
+    #[case(TriangleAdjacency::Edges, 6, &[5, 7, 11])]
+    #[case(TriangleAdjacency::Edges, 7, &[2, 6, 8])]
+    #[case(TriangleAdjacency::Edges, 24, &[23])]
+    #[case(TriangleAdjacency::Vertices, 0, &[1, 2, 5, 6, 7])]
+    #[case(TriangleAdjacency::Vertices, 6, &[0, 1, 2, 5, 7, 8, 10, 11, 12, 13])]
+    #[case(TriangleAdjacency::Vertices, 12, &[6, 7, 8, 10, 11, 13, 14, 15, 16, 17, 18, 19])]
+    #[case(TriangleAdjacency::Vertices, 13, &[6, 7, 8, 9, 11, 12, 14, 17, 18, 19])]
+    fn triangle_neighbors_return_expected_results(
+        #[case] adjacency: TriangleAdjacency,
+        #[case] idx: usize,
+        #[case] expected_indexes: &[usize],
+    ) {
+        let neighbor_ctx = NeighboringContext::new(
+            25,
+            5,
+            NeighboringStrategy::Triangle(adjacency),
+            Boundary::Open,
+        );
+
+        let actual_indexes = cell_indexes(neighbor_ctx.get_neighbors(idx));
+
+        assert_eq!(actual_indexes, expected_indexes);
+    }
+
+    #[test]
+    fn radius_one_matches_fixed_neighborhoods() {
+        let fixed =
+            NeighboringContext::new(9, 3, NeighboringStrategy::SquareAndCorners, Boundary::Open);
+        let moore = NeighboringContext::new(
+            9,
+            3,
+            NeighboringStrategy::Moore { radius: 1 },
+            Boundary::Open,
+        );
+        let von_neumann = NeighboringContext::new(
+            9,
+            3,
+            NeighboringStrategy::VonNeumann { radius: 1 },
+            Boundary::Open,
+        );
+        let square = NeighboringContext::new(9, 3, NeighboringStrategy::Square, Boundary::Open);
+
+        for idx in 0..9 {
+            assert_eq!(
+                cell_indexes(fixed.get_neighbors(idx)),
+                cell_indexes(moore.get_neighbors(idx))
+            );
+            assert_eq!(
+                cell_indexes(square.get_neighbors(idx)),
+                cell_indexes(von_neumann.get_neighbors(idx))
+            );
+        }
+    }
+
+    #[test]
+    fn radius_iter_skips_the_center() {
+        let offsets: Vec<_> = RadiusIter::new(2, 0, true)
+            .map(|(dx, dy, _)| (dx, dy))
+            .collect();
+
+        assert!(!offsets.contains(&(0, 0)));
+        assert_eq!(offsets.len(), 12);
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::SquareAndCorners, 13, 26)]
+    #[case(NeighboringStrategy::Moore { radius: 1 }, 0, 7)]
+    #[case(NeighboringStrategy::Square, 13, 6)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 1 }, 0, 3)]
+    #[case(NeighboringStrategy::Moore { radius: 2 }, 13, 26)]
+    #[case(NeighboringStrategy::Hexagon(HexLayout::OddRows), 13, 6)]
+    fn volumetric_neighborhoods_return_expected_counts(
+        #[case] strategy: NeighboringStrategy,
+        #[case] idx: usize,
+        #[case] expected_count: usize,
+    ) {
+        let mut neighbor_ctx = NeighboringContext::new(27, 3, strategy, Boundary::Open);
+        neighbor_ctx.depth = 3;
+
+        assert_eq!(neighbor_ctx.get_neighbors(idx).count(), expected_count);
+    }
+
+    #[test]
+    fn von_neumann_reaches_adjacent_slices() {
+        let mut neighbor_ctx =
+            NeighboringContext::new(27, 3, NeighboringStrategy::Square, Boundary::Toroidal);
+        neighbor_ctx.depth = 3;
+
+        assert_eq!(neighbor_ctx.coords(22), (1, 1, 2));
+        assert_eq!(
+            cell_indexes(neighbor_ctx.get_neighbors(4)),
+            &[1, 3, 5, 7, 13, 22]
+        );
+    }
+
+    #[rstest]
+    #[case(NeighboringStrategy::Moore { radius: 1 }, 40, 80)]
+    #[case(NeighboringStrategy::Moore { radius: 1 }, 0, 15)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 1 }, 40, 8)]
+    #[case(NeighboringStrategy::VonNeumann { radius: 2 }, 40, 32)]
+    #[case(NeighboringStrategy::SquareAndCorners, 40, 80)]
+    fn hyper_neighborhoods_reach_along_every_axis(
+        #[case] strategy: NeighboringStrategy,
+        #[case] idx: usize,
+        #[case] expected_count: usize,
+    ) {
+        let mut neighbor_ctx = NeighboringContext::new(81, 3, strategy, Boundary::Open);
+        neighbor_ctx.depth = 9;
+        neighbor_ctx.hyper_extents = vec![3];
+
+        assert_eq!(neighbor_ctx.extents(), [3, 3, 3, 3]);
+        assert_eq!(neighbor_ctx.position(40), [1, 1, 1, 1]);
+        assert_eq!(neighbor_ctx.get_neighbors(idx).count(), expected_count);
+    }
+
+    #[rstest]
+    #[case(Boundary::Toroidal, &[0, 0, 2, 1], Neighbor::Cell(8))]
+    #[case(Boundary::Toroidal, &[1, 1, 1, -1], Neighbor::Cell(15))]
+    #[case(Boundary::Reflective, &[1, 0, 0, 2], Neighbor::Cell(9))]
+    #[case(Boundary::Open, &[0, 0, 0, 2], Neighbor::Missing)]
+    fn hyper_boundaries_apply_to_each_axis_on_its_own(
+        #[case] boundary: Boundary,
+        #[case] position: &[isize],
+        #[case] expected: Neighbor,
+    ) {
+        let mut neighbor_ctx =
+            NeighboringContext::new(16, 2, NeighboringStrategy::Square, boundary);
+        neighbor_ctx.depth = 4;
+        neighbor_ctx.hyper_extents = vec![2];
+
+        assert_eq!(neighbor_ctx.resolve(position), expected);
+    }
+
+    #[rstest]
+    #[case(Boundary::Open, &[1, 3, 4], 0)]
+    #[case(Boundary::Toroidal, &[1, 2, 3, 4, 5, 6, 7, 8], 0)]
+    #[case(Boundary::Reflective, &[0, 0, 0, 1, 1, 3, 3, 4], 0)]
+    #[case(Boundary::Fixed(NodeId(1)), &[1, 3, 4], 5)]
+    #[case(Boundary::Cylinder, &[1, 2, 3, 4, 5], 0)]
+    #[case(Boundary::Mobius, &[1, 3, 4, 5, 8], 0)]
+    #[case(Boundary::KleinBottle, &[1, 2, 3, 4, 5, 6, 7, 8], 0)]
+    fn corner_neighbors_go_through_the_boundary(
+        #[case] boundary: Boundary,
+        #[case] expected_indexes: &[usize],
+        #[case] expected_virtual: usize,
+    ) {
+        let neighbor_ctx =
+            NeighboringContext::new(9, 3, NeighboringStrategy::SquareAndCorners, boundary);
+
+        let actual_indexes = cell_indexes(neighbor_ctx.get_neighbors(0));
+        let actual_virtual = neighbor_ctx
+            .get_neighbors(0)
+            .filter(|neighbor| matches!(neighbor, Neighbor::Virtual(_)))
+            .count();
 
         assert_eq!(actual_indexes, expected_indexes);
+        assert_eq!(actual_virtual, expected_virtual);
     }
 }
@@ -0,0 +1,263 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::ensure;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{model::NodeId, state_map::StateMap};
+
+use super::{neighbor_strategy::NeighboringStrategy, Grid};
+
+/// Cells along each side of a chunk
+pub const CHUNK_SIZE: usize = 16;
+
+const CHUNK_LEN: usize = CHUNK_SIZE * CHUNK_SIZE;
+
+/// Chunk coordinates, as `(x, y)` in units of [`CHUNK_SIZE`] cells
+type ChunkKey = (isize, isize);
+
+type Chunk = Box<[NodeId; CHUNK_LEN]>;
+
+/// Unbounded planar grid, where only chunks holding some cell out of the
+/// background state are stored.
+///
+/// Chunks are allocated as the pattern spreads into them and dropped once
+/// they fall back to the background, so the active region follows the
+/// pattern around the plane.
+#[derive(Debug, Clone)]
+pub struct SparseGrid {
+    strategy: NeighboringStrategy,
+    background: NodeId,
+    chunks: HashMap<ChunkKey, Chunk>,
+}
+
+impl SparseGrid {
+    pub fn new(strategy: NeighboringStrategy, background: NodeId) -> Self {
+        Self {
+            strategy,
+            background,
+            chunks: HashMap::new(),
+        }
+    }
+
+    /// Copies the cells of a planar grid, placing its top-left corner at the
+    /// origin
+    pub fn from_grid(grid: &Grid, background: NodeId) -> anyhow::Result<Self> {
+        ensure!(
+            !grid.neighbor_ctx().is_volumetric(),
+            "unbounded grids are planar, but the grid is {} slices deep",
+            grid.depth()
+        );
+
+        let mut sparse = Self::new(grid.neighbor_ctx().strategy().clone(), background);
+        grid.cells()
+            .chunks(grid.cells_per_row())
+            .enumerate()
+            .flat_map(|(y, row)| row.iter().enumerate().map(move |(x, cell)| (x, y, *cell)))
+            .for_each(|(x, y, cell)| sparse.set(x as isize, y as isize, cell));
+
+        Ok(sparse)
+    }
+
+    /// Crops the active region into a bounded grid, or `None` when every
+    /// cell is in the background state
+    pub fn to_grid(&self) -> Option<Grid> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds()?;
+        let width = (max_x - min_x + 1) as usize;
+        let height = (max_y - min_y + 1) as usize;
+
+        let mut grid = Grid::empty(width * height, width, self.strategy.clone());
+        for (idx, (x, y)) in (min_y..=max_y)
+            .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+            .enumerate()
+        {
+            grid.set_cell_at(idx, self.get(x, y));
+        }
+
+        Some(grid)
+    }
+
+    #[inline]
+    pub fn get(&self, x: isize, y: isize) -> NodeId {
+        let (key, offset) = Self::locate(x, y);
+        self.chunks
+            .get(&key)
+            .map_or(self.background, |chunk| chunk[offset])
+    }
+
+    pub fn set(&mut self, x: isize, y: isize, state: NodeId) {
+        let (key, offset) = Self::locate(x, y);
+
+        if let Some(chunk) = self.chunks.get_mut(&key) {
+            chunk[offset] = state;
+            if chunk.iter().all(|cell| *cell == self.background) {
+                self.chunks.remove(&key);
+            }
+        } else if state != self.background {
+            let mut chunk = Box::new([self.background; CHUNK_LEN]);
+            chunk[offset] = state;
+            self.chunks.insert(key, chunk);
+        }
+    }
+
+    /// Smallest and largest coordinates of cells out of the background
+    /// state, or `None` when there are none
+    pub fn bounds(&self) -> Option<((isize, isize), (isize, isize))> {
+        self.cells()
+            .map(|(x, y, _)| ((x, y), (x, y)))
+            .reduce(|(min, max), (pos, _)| {
+                (
+                    (min.0.min(pos.0), min.1.min(pos.1)),
+                    (max.0.max(pos.0), max.1.max(pos.1)),
+                )
+            })
+    }
+
+    /// Cells out of the background state, as `(x, y, state)`, in no
+    /// particular order
+    pub fn cells(&self) -> impl Iterator<Item = (isize, isize, NodeId)> + '_ {
+        self.chunks.iter().flat_map(|(&(chunk_x, chunk_y), chunk)| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, cell)| **cell != self.background)
+                .map(move |(offset, cell)| {
+                    let x = chunk_x * CHUNK_SIZE as isize + (offset % CHUNK_SIZE) as isize;
+                    let y = chunk_y * CHUNK_SIZE as isize + (offset / CHUNK_SIZE) as isize;
+                    (x, y, *cell)
+                })
+        })
+    }
+
+    /// How many cells are in each state. The background is left out, as
+    /// there are infinitely many of those.
+    pub fn population(&self) -> BTreeMap<NodeId, usize> {
+        let mut population = BTreeMap::new();
+        self.cells()
+            .for_each(|(_, _, cell)| *population.entry(cell).or_default() += 1);
+
+        population
+    }
+
+    /// Number of chunks currently allocated
+    #[inline]
+    pub fn n_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    #[inline]
+    pub fn background(&self) -> NodeId {
+        self.background
+    }
+
+    #[inline]
+    pub fn strategy(&self) -> &NeighboringStrategy {
+        &self.strategy
+    }
+
+    pub fn set_strategy(&mut self, strategy: NeighboringStrategy) {
+        self.strategy = strategy;
+    }
+
+    #[inline]
+    pub fn iter_neighbors(&self, x: isize, y: isize) -> impl Iterator<Item = NodeId> + '_ {
+        self.strategy
+            .planar_offsets(x, y)
+            .map(move |(dx, dy, _)| self.get(x + dx, y + dy))
+    }
+
+    /// Evaluates `f` for every cell that may leave the background state,
+    /// which are those in stored chunks or close enough to reach them, and
+    /// moves on to the next generation.
+    ///
+    /// `f` must keep background cells surrounded by background in the
+    /// background state, or the pattern would fill the whole plane.
+    pub(crate) fn map_cells<F>(&mut self, n_states: usize, f: F)
+    where
+        F: Fn(NodeId, &StateMap) -> NodeId + Send + Sync,
+    {
+        let reach = self.strategy.reach().div_ceil(CHUNK_SIZE) as isize;
+        let candidates: HashSet<_> = self
+            .chunks
+            .keys()
+            .flat_map(|&(chunk_x, chunk_y)| {
+                (-reach..=reach).flat_map(move |dy| {
+                    (-reach..=reach).map(move |dx| (chunk_x + dx, chunk_y + dy))
+                })
+            })
+            .collect();
+
+        let next_chunks = candidates
+            .into_par_iter()
+            .map_init(
+                || StateMap::with_states(n_states),
+                |state_map, key| {
+                    let mut chunk = Box::new([self.background; CHUNK_LEN]);
+                    for (offset, next_cell) in chunk.iter_mut().enumerate() {
+                        let x = key.0 * CHUNK_SIZE as isize + (offset % CHUNK_SIZE) as isize;
+                        let y = key.1 * CHUNK_SIZE as isize + (offset / CHUNK_SIZE) as isize;
+
+                        state_map.count_states(self.iter_neighbors(x, y));
+                        *next_cell = f(self.get(x, y), state_map);
+                    }
+
+                    (key, chunk)
+                },
+            )
+            .filter(|(_, chunk)| chunk.iter().any(|cell| *cell != self.background))
+            .collect();
+
+        self.chunks = next_chunks;
+    }
+
+    /// Splits coordinates into the chunk holding them and the offset within
+    /// that chunk
+    #[inline]
+    fn locate(x: isize, y: isize) -> (ChunkKey, usize) {
+        let size = CHUNK_SIZE as isize;
+        let key = (x.div_euclid(size), y.div_euclid(size));
+        let offset = y.rem_euclid(size) * size + x.rem_euclid(size);
+
+        (key, offset as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::test_utils::game_of_life_grid;
+
+    #[test]
+    fn chunks_should_be_dropped_once_back_to_background() {
+        let mut grid = SparseGrid::new(NeighboringStrategy::SquareAndCorners, NodeId(0));
+
+        grid.set(-1, -1, NodeId(1));
+        grid.set(40, 3, NodeId(1));
+        assert_eq!(grid.n_chunks(), 2);
+        assert_eq!(grid.bounds(), Some(((-1, -1), (40, 3))));
+
+        grid.set(-1, -1, NodeId(0));
+        assert_eq!(grid.n_chunks(), 1);
+        assert_eq!(grid.bounds(), Some(((40, 3), (40, 3))));
+    }
+
+    #[test]
+    fn grid_conversion_should_crop_to_the_active_region() {
+        let grid = game_of_life_grid(
+            "
+            ░░░░
+            ░█░░
+            ░░█░
+            ░░░░
+        ",
+        );
+
+        let sparse = SparseGrid::from_grid(&grid, NodeId(0)).unwrap();
+        let cropped = sparse.to_grid().unwrap();
+
+        assert_eq!(sparse.bounds(), Some(((1, 1), (2, 2))));
+        assert_eq!(
+            cropped.cells(),
+            &[NodeId(1), NodeId(0), NodeId(0), NodeId(1)]
+        );
+    }
+}
@@ -1,10 +1,13 @@
 use crate::model::NodeId;
 
-use super::{neighbor_strategy::NeighboringStrategy, Grid};
+use super::{
+    neighbor_strategy::{Boundary, NeighboringStrategy},
+    Grid, Initializer, StateProbabilty,
+};
 
 const IGNORED_CHARS: &[char] = &['\n', ' '];
 
-pub fn grid_from_repr(repr: &'static str, map: impl Fn(char) -> NodeId) -> Grid {
+pub fn grid_from_repr(repr: &str, map: impl Fn(char) -> NodeId) -> Grid {
     let height = repr.trim_matches(IGNORED_CHARS).lines().count();
 
     let clean_repr = repr.chars().filter(|c| !IGNORED_CHARS.contains(c));
@@ -31,7 +34,7 @@ pub fn grid_to_repr<F>(grid: &Grid, map: F) -> String
 where
     F: FnMut(NodeId) -> char + Clone + Copy,
 {
-    grid.cells
+    grid.cells()
         .chunks(grid.cells_per_row())
         .map(|chunk| String::from_iter(chunk.iter().copied().map(map)))
         .collect::<Vec<_>>()
@@ -43,7 +46,7 @@ where
 // ▓ 9619
 // █ 9608
 
-pub fn game_of_life_grid(repr: &'static str) -> Grid {
+pub fn game_of_life_grid(repr: &str) -> Grid {
     grid_from_repr(repr, |c| match c {
         '░' => NodeId(0),
         '█' => NodeId(1),
@@ -58,3 +61,28 @@ pub fn to_game_of_life_output(grid: &Grid) -> String {
         _ => panic!("Invalid state NodeId({})", state.0),
     })
 }
+
+/// Toroidal Game of Life grid where each cell is alive with probability
+/// `density`, drawn from `seed`
+pub fn game_of_life_soup(width: usize, height: usize, density: f32, seed: u64) -> Grid {
+    let mut grid = Grid::empty(width * height, width, NeighboringStrategy::SquareAndCorners);
+    grid.set_boundary(Boundary::Toroidal);
+    grid.initialize(
+        Initializer::Random {
+            states: vec![
+                StateProbabilty {
+                    state: NodeId(0),
+                    weight: 1.0 - density,
+                },
+                StateProbabilty {
+                    state: NodeId(1),
+                    weight: density,
+                },
+            ],
+        },
+        seed,
+    )
+    .unwrap();
+
+    grid
+}
@@ -0,0 +1,330 @@
+use anyhow::ensure;
+
+use crate::model::NodeId;
+
+use super::{Axis, Grid};
+
+/// Which part of a grid keeps its place when it's resized
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Anchor {
+    #[default]
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    /// Where the old cells start along `x` and `y`, relative to the new ones,
+    /// when going from `from` to `to` cells along each axis
+    fn offsets(self, from: (usize, usize), to: (usize, usize)) -> (isize, isize) {
+        // Halves of the size difference on the left or top
+        let (column, row) = match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        };
+        let offset =
+            |from: usize, to: usize, halves: isize| (to as isize - from as isize) * halves / 2;
+
+        (offset(from.0, to.0, column), offset(from.1, to.1, row))
+    }
+}
+
+/// What happens to cells shifted past the edges of a grid
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Overflow {
+    /// They come back in on the opposite side
+    Wrap,
+    /// They're dropped, and the cells left behind take the given state
+    Clip(NodeId),
+}
+
+/// Clockwise rotation by a multiple of a quarter turn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+/// Whole-grid transforms. Each builds a new grid with the same neighboring
+/// strategy, boundary and depth, and applies to every slice of volumetric
+/// grids alike.
+impl Grid {
+    /// Grid of `width` by `height` cells, where the old cells keep their place
+    /// relative to `anchor`. Cells that no longer fit are dropped, and new
+    /// ones take the `padding` state.
+    pub fn resized(
+        &self,
+        width: usize,
+        height: usize,
+        anchor: Anchor,
+        padding: NodeId,
+    ) -> anyhow::Result<Grid> {
+        ensure!(
+            width > 0 && height > 0,
+            "grids must be at least 1 cell wide and high, got {width}x{height}"
+        );
+
+        let (dx, dy) = anchor.offsets((self.cells_per_row(), self.n_rows()), (width, height));
+        Ok(self.remapped(width, height, |x, y, z| {
+            self.cell_at(x as isize - dx, y as isize - dy, z)
+                .unwrap_or(padding)
+        }))
+    }
+
+    /// Smallest grid holding every cell out of the `background` state, or
+    /// `None` when there are none
+    pub fn cropped(&self, background: NodeId) -> Option<Grid> {
+        let (min, max) = self
+            .cells()
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| **cell != background)
+            .map(|(idx, _)| self.neighbor_ctx.coords(idx))
+            .map(|(x, y, _)| ((x, y), (x, y)))
+            .reduce(|(min, max), (pos, _)| {
+                (
+                    (min.0.min(pos.0), min.1.min(pos.1)),
+                    (max.0.max(pos.0), max.1.max(pos.1)),
+                )
+            })?;
+
+        Some(
+            self.remapped(max.0 - min.0 + 1, max.1 - min.1 + 1, |x, y, z| {
+                self.cell_at((min.0 + x) as isize, (min.1 + y) as isize, z)
+                    .unwrap_or(background)
+            }),
+        )
+    }
+
+    /// Grid with every cell moved `dx` columns right and `dy` rows down
+    pub fn translated(&self, dx: isize, dy: isize, overflow: Overflow) -> Grid {
+        let width = self.cells_per_row();
+        let height = self.n_rows();
+
+        self.remapped(width, height, |x, y, z| {
+            let (from_x, from_y) = (x as isize - dx, y as isize - dy);
+            match overflow {
+                Overflow::Wrap => self.cell_at(
+                    from_x.rem_euclid(width as isize),
+                    from_y.rem_euclid(height as isize),
+                    z,
+                ),
+                Overflow::Clip(padding) => Some(self.cell_at(from_x, from_y, z).unwrap_or(padding)),
+            }
+            .unwrap_or_default()
+        })
+    }
+
+    /// Grid turned clockwise by `rotation`. Quarter turns swap its width and
+    /// height.
+    pub fn rotated(&self, rotation: Rotation) -> Grid {
+        let width = self.cells_per_row();
+        let height = self.n_rows();
+        let (last_x, last_y) = (width as isize - 1, height as isize - 1);
+
+        match rotation {
+            Rotation::Quarter => self.remapped(height, width, |x, y, z| {
+                self.cell_at(y as isize, last_y - x as isize, z)
+                    .unwrap_or_default()
+            }),
+            Rotation::Half => self.remapped(width, height, |x, y, z| {
+                self.cell_at(last_x - x as isize, last_y - y as isize, z)
+                    .unwrap_or_default()
+            }),
+            Rotation::ThreeQuarters => self.remapped(height, width, |x, y, z| {
+                self.cell_at(last_x - y as isize, x as isize, z)
+                    .unwrap_or_default()
+            }),
+        }
+    }
+
+    /// Grid mirrored along `axis`, so that [`Axis::X`] swaps its left and
+    /// right sides. Axes past `z` are left as they are.
+    pub fn reflected(&self, axis: Axis) -> Grid {
+        let [width, height, _] = self.slice_extents();
+        let z_len = self.extents()[2];
+
+        self.remapped(width, height, |x, y, slice| {
+            let (x, y, slice) = match axis {
+                Axis::X => (width - 1 - x, y, slice),
+                Axis::Y => (x, height - 1 - y, slice),
+                Axis::Z => (x, y, slice - slice % z_len + z_len - 1 - slice % z_len),
+            };
+            self.cell_at(x as isize, y as isize, slice)
+                .unwrap_or_default()
+        })
+    }
+
+    /// Cell at `(x, y)` in slice `z`, or `None` past the edges
+    fn cell_at(&self, x: isize, y: isize, z: usize) -> Option<NodeId> {
+        let width = self.cells_per_row() as isize;
+        if !(0..width).contains(&x) || !(0..self.n_rows() as isize).contains(&y) {
+            return None;
+        }
+
+        let idx = z * self.neighbor_ctx.slice_len() + (y * width + x) as usize;
+        self.cells().get(idx).copied()
+    }
+
+    /// Grid of `width` by `height` cells in each slice, sharing this grid's
+    /// neighboring context, with cells taken from `f(x, y, z)`
+    fn remapped(
+        &self,
+        width: usize,
+        height: usize,
+        f: impl Fn(usize, usize, usize) -> NodeId,
+    ) -> Grid {
+        let depth = self.depth();
+        let mut grid = Grid::empty(
+            width * height * depth,
+            width,
+            self.neighbor_ctx.strategy.clone(),
+        );
+        grid.neighbor_ctx.boundary = self.boundary();
+        grid.neighbor_ctx.depth = depth;
+        grid.neighbor_ctx.hyper_extents = self.neighbor_ctx.hyper_extents.clone();
+
+        grid.cells.clear();
+        (0..depth)
+            .flat_map(|z| (0..height).flat_map(move |y| (0..width).map(move |x| (x, y, z))))
+            .map(|(x, y, z)| f(x, y, z))
+            .collect_into(&mut grid.cells);
+
+        grid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{
+        neighbor_strategy::{Boundary, NeighboringStrategy},
+        test_utils::{game_of_life_grid, to_game_of_life_output},
+    };
+    use rstest::rstest;
+
+    const L_SHAPE: &str = "
+        █░░
+        █░░
+        ██░
+    ";
+
+    #[rstest]
+    #[case(Anchor::TopLeft, "█░░░\n█░░░\n██░░\n░░░░\n░░░░")]
+    #[case(Anchor::Center, "░░░░\n█░░░\n█░░░\n██░░\n░░░░")]
+    #[case(Anchor::BottomRight, "░░░░\n░░░░\n░█░░\n░█░░\n░██░")]
+    fn resize_should_keep_cells_next_to_the_anchor(#[case] anchor: Anchor, #[case] expected: &str) {
+        let mut grid = game_of_life_grid(L_SHAPE);
+        grid.set_boundary(Boundary::Toroidal);
+
+        let resized = grid.resized(4, 5, anchor, NodeId(0)).unwrap();
+
+        assert_eq!(to_game_of_life_output(&resized), expected);
+        assert_eq!(resized.boundary(), Boundary::Toroidal);
+        assert_eq!(resized.n_cells(), 20);
+        assert!(grid.resized(0, 5, anchor, NodeId(0)).is_err());
+    }
+
+    #[test]
+    fn shrinking_should_drop_cells_past_the_edges() {
+        let grid = game_of_life_grid(L_SHAPE);
+
+        let resized = grid.resized(2, 2, Anchor::BottomRight, NodeId(0)).unwrap();
+
+        assert_eq!(to_game_of_life_output(&resized), "░░\n█░");
+    }
+
+    #[test]
+    fn crop_should_keep_the_bounding_box() {
+        let grid = game_of_life_grid(
+            "
+            ░░░░░
+            ░░█░░
+            ░░░█░
+            ░░░░░
+        ",
+        );
+
+        let cropped = grid.cropped(NodeId(0)).unwrap();
+
+        assert_eq!(to_game_of_life_output(&cropped), "█░\n░█");
+        assert!(game_of_life_grid("░░\n░░").cropped(NodeId(0)).is_none());
+    }
+
+    #[rstest]
+    #[case(Overflow::Wrap, "░██\n░█░\n░█░")]
+    #[case(Overflow::Clip(NodeId(0)), "░░░\n░█░\n░█░")]
+    fn translate_should_wrap_or_clip(#[case] overflow: Overflow, #[case] expected: &str) {
+        let grid = game_of_life_grid(L_SHAPE);
+
+        let translated = grid.translated(1, 1, overflow);
+
+        assert_eq!(to_game_of_life_output(&translated), expected);
+    }
+
+    #[rstest]
+    #[case(Rotation::Quarter, "░█\n░█\n░░\n█░")]
+    #[case(Rotation::Half, "█░░░\n░░██")]
+    #[case(Rotation::ThreeQuarters, "░█\n░░\n█░\n█░")]
+    fn rotate_should_turn_clockwise(#[case] rotation: Rotation, #[case] expected: &str) {
+        let grid = game_of_life_grid(
+            "
+            ██░░
+            ░░░█
+        ",
+        );
+
+        let rotated = grid.rotated(rotation);
+
+        assert_eq!(to_game_of_life_output(&rotated), expected);
+    }
+
+    #[test]
+    fn reflect_should_mirror_each_axis() {
+        let grid = game_of_life_grid(L_SHAPE);
+        assert_eq!(
+            to_game_of_life_output(&grid.reflected(Axis::X)),
+            "░░█\n░░█\n░██"
+        );
+        assert_eq!(
+            to_game_of_life_output(&grid.reflected(Axis::Y)),
+            "██░\n█░░\n█░░"
+        );
+
+        let mut volumetric =
+            Grid::with_extents(&[2, 1, 2], NeighboringStrategy::Moore { radius: 1 }).unwrap();
+        volumetric.set_cell_at(0, NodeId(1));
+        let reflected = volumetric.reflected(Axis::Z);
+
+        assert_eq!(reflected.extents(), [2, 1, 2]);
+        assert_eq!(
+            reflected.cells(),
+            &[NodeId(0), NodeId(0), NodeId(1), NodeId(0)]
+        );
+
+        let mut hyper =
+            Grid::with_extents(&[1, 1, 2, 2], NeighboringStrategy::Moore { radius: 1 }).unwrap();
+        hyper.set_cell_at(2, NodeId(1));
+        let reflected = hyper.reflected(Axis::Z);
+
+        assert_eq!(reflected.extents(), [1, 1, 2, 2]);
+        assert_eq!(
+            reflected.cells(),
+            &[NodeId(0), NodeId(0), NodeId(0), NodeId(1)]
+        );
+    }
+}
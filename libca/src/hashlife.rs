@@ -0,0 +1,646 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::ensure;
+
+use crate::{
+    grid::{neighbor_strategy::NeighboringStrategy, Grid},
+    layer::CellContext,
+    model::{Model, NodeId},
+    simulation::Stepper,
+    state_map::StateMap,
+};
+
+/// Smallest and largest coordinates of a region, as `((x, y), (x, y))`
+type Bounds = ((i64, i64), (i64, i64));
+
+/// Index of a node in the [`HashLife`] arena
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+struct QuadId(usize);
+
+/// Square of `2^level` cells per side. Identical squares are stored once, so
+/// nodes are equal if and only if their ids are.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum Quad {
+    Leaf(NodeId),
+    /// Children in reading order: top-left, top-right, bottom-left and
+    /// bottom-right
+    Branch {
+        level: u32,
+        children: [QuadId; 4],
+    },
+}
+
+impl Quad {
+    #[inline]
+    fn level(&self) -> u32 {
+        match self {
+            Quad::Leaf(_) => 0,
+            Quad::Branch { level, .. } => *level,
+        }
+    }
+}
+
+/// Steps a model on an unbounded plane through a memoized quadtree, jumping
+/// ahead a power of two generations at a time.
+///
+/// A node `2^k` cells wide knows its center, half as wide, up to
+/// `2^(k - 2 - m)` generations ahead, where `2^m` is the smallest power of two
+/// covering the neighborhood's reach. As every result is memoized, repetitive
+/// patterns are stepped in time logarithmic in the number of generations.
+///
+/// Only position-independent neighborhoods are supported, and like unbounded
+/// [`crate::grid::sparse::SparseGrid`]s, the background state must be stable
+/// on its own. Nodes are never freed, so memory grows with the history of the
+/// pattern.
+pub struct HashLife {
+    model: Model,
+    strategy: NeighboringStrategy,
+    background: NodeId,
+    /// `log2` of the smallest power of two covering the neighborhood's reach
+    reach_log2: u32,
+    quads: Vec<Quad>,
+    ids: HashMap<Quad, QuadId>,
+    /// Empty node of each level, by level
+    empty: Vec<QuadId>,
+    /// Center of a node after `2^j` generations, by node and `j`
+    successors: HashMap<(QuadId, u32), QuadId>,
+    root: QuadId,
+    /// Coordinates of the root's top-left cell
+    origin: (i64, i64),
+    generation: u64,
+}
+
+impl HashLife {
+    /// Loads a planar grid, placing its top-left corner at the origin.
+    /// Cells outside of it are in the `background` state.
+    pub fn from_grid(model: Model, grid: &Grid, background: NodeId) -> anyhow::Result<Self> {
+        ensure!(
+            !grid.neighbor_ctx().is_volumetric(),
+            "HashLife grids are planar, but the grid is {} slices deep",
+            grid.depth()
+        );
+        // Quads are shared between places and generations, so cells can only
+        // look at their neighbors on the same layer
+        ensure!(
+            model.is_outer_totalistic(),
+            "HashLife only supports transitions counting neighbors on their own layer"
+        );
+
+        let strategy = model
+            .neighborhood()
+            .unwrap_or(grid.neighbor_ctx().strategy())
+            .clone();
+        ensure!(
+            !matches!(
+                strategy,
+                NeighboringStrategy::Hexagon(_) | NeighboringStrategy::Triangle(_)
+            ),
+            "HashLife needs neighborhoods that look the same from every cell"
+        );
+
+        let reach_log2 = strategy.reach().max(1).next_power_of_two().ilog2();
+
+        let mut hashlife = Self {
+            model,
+            strategy,
+            background,
+            reach_log2,
+            quads: Vec::new(),
+            ids: HashMap::new(),
+            empty: Vec::new(),
+            successors: HashMap::new(),
+            root: QuadId(0),
+            origin: (0, 0),
+            generation: 0,
+        };
+
+        let mut state_map = StateMap::new();
+        let n_neighbors = hashlife.strategy.planar_offsets(0, 0).count();
+        state_map.count_states(std::iter::repeat_n(background, n_neighbors));
+        ensure!(
+            hashlife.next_state(background, &state_map) == background,
+            "the background state must be stable on its own"
+        );
+
+        let [width, height, _] = grid.slice_extents();
+        let level = width
+            .max(height)
+            .next_power_of_two()
+            .ilog2()
+            .max(hashlife.base_level());
+        let side = 1 << level;
+        let cells: Vec<_> = (0..side)
+            .flat_map(|y| (0..side).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                if x < width && y < height {
+                    grid.cells()
+                        .get(y * width + x)
+                        .copied()
+                        .unwrap_or(background)
+                } else {
+                    background
+                }
+            })
+            .collect();
+        hashlife.root = hashlife.intern_cells(&cells, side, 0, 0, level);
+
+        Ok(hashlife)
+    }
+
+    /// Crops the pattern into a bounded grid, or `None` when every cell is
+    /// in the background state
+    pub fn to_grid(&self) -> Option<Grid> {
+        let ((min_x, min_y), (max_x, max_y)) = self.bounds()?;
+        let width = (max_x - min_x + 1) as usize;
+        let height = (max_y - min_y + 1) as usize;
+
+        let mut grid = Grid::empty(width * height, width, self.strategy.clone());
+        for (idx, (x, y)) in (min_y..=max_y)
+            .flat_map(|y| (min_x..=max_x).map(move |x| (x, y)))
+            .enumerate()
+        {
+            grid.set_cell_at(idx, self.get(x, y));
+        }
+
+        Some(grid)
+    }
+
+    /// Advances the pattern by any number of generations, one power of two
+    /// at a time
+    pub fn step_by(&mut self, generations: u64) {
+        (0..u64::BITS)
+            .filter(|j| generations & (1 << j) != 0)
+            .for_each(|j| self.step_by_power_of_two(j));
+    }
+
+    /// Advances the pattern by `2^j` generations
+    pub fn step_by_power_of_two(&mut self, j: u32) {
+        // Once the pattern sits within the root's central half, a single
+        // expansion leaves enough room for it to grow into for 2^j steps
+        while self.level(self.root) < j + self.base_level() || !self.is_root_centered() {
+            self.expand();
+        }
+        self.expand();
+
+        let level = self.level(self.root);
+        self.root = self.successor(self.root, j);
+        let quarter = 1 << (level - 2);
+        self.origin = (self.origin.0 + quarter, self.origin.1 + quarter);
+        self.generation += 1 << j;
+    }
+
+    pub fn get(&self, x: i64, y: i64) -> NodeId {
+        let side = 1i64 << self.level(self.root);
+        let (mut x, mut y) = (x - self.origin.0, y - self.origin.1);
+        if !(0..side).contains(&x) || !(0..side).contains(&y) {
+            return self.background;
+        }
+
+        let mut quad = self.quads[self.root.0];
+        loop {
+            match quad {
+                Quad::Leaf(state) => return state,
+                Quad::Branch { level, children } => {
+                    let half = 1 << (level - 1);
+                    let child = (y >= half) as usize * 2 + (x >= half) as usize;
+                    x %= half;
+                    y %= half;
+                    quad = self.quads[children[child].0];
+                }
+            }
+        }
+    }
+
+    /// Smallest and largest coordinates of cells out of the background
+    /// state, or `None` when there are none
+    pub fn bounds(&self) -> Option<Bounds> {
+        let mut bounds = None;
+        self.visit_bounds(self.root, self.origin, &mut bounds);
+
+        bounds
+    }
+
+    /// Number of distinct nodes stored so far
+    #[inline]
+    pub fn n_nodes(&self) -> usize {
+        self.quads.len()
+    }
+
+    #[inline]
+    pub fn background(&self) -> NodeId {
+        self.background
+    }
+
+    /// Level of the smallest node whose center is stepped by brute force
+    #[inline]
+    fn base_level(&self) -> u32 {
+        self.reach_log2 + 2
+    }
+
+    #[inline]
+    fn level(&self, id: QuadId) -> u32 {
+        self.quads[id.0].level()
+    }
+
+    fn children(&self, id: QuadId) -> [QuadId; 4] {
+        match self.quads[id.0] {
+            Quad::Branch { children, .. } => children,
+            Quad::Leaf(_) => unreachable!("leaves have no children"),
+        }
+    }
+
+    /// Canonical id of `quad`, storing it if it's new
+    fn intern(&mut self, quad: Quad) -> QuadId {
+        if let Some(id) = self.ids.get(&quad) {
+            return *id;
+        }
+
+        let id = QuadId(self.quads.len());
+        self.quads.push(quad);
+        self.ids.insert(quad, id);
+
+        id
+    }
+
+    fn join(&mut self, children: [QuadId; 4]) -> QuadId {
+        let level = self.level(children[0]) + 1;
+        self.intern(Quad::Branch { level, children })
+    }
+
+    fn empty(&mut self, level: u32) -> QuadId {
+        while self.empty.len() <= level as usize {
+            let quad = match self.empty.last() {
+                None => Quad::Leaf(self.background),
+                Some(&child) => Quad::Branch {
+                    level: self.empty.len() as u32,
+                    children: [child; 4],
+                },
+            };
+            let id = self.intern(quad);
+            self.empty.push(id);
+        }
+
+        self.empty[level as usize]
+    }
+
+    fn is_empty(&mut self, id: QuadId) -> bool {
+        let level = self.level(id);
+        self.empty(level) == id
+    }
+
+    /// Builds the node for the `2^level` square whose top-left corner is at
+    /// `(x, y)` in `cells`
+    fn intern_cells(
+        &mut self,
+        cells: &[NodeId],
+        side: usize,
+        x: usize,
+        y: usize,
+        level: u32,
+    ) -> QuadId {
+        if level == 0 {
+            return self.intern(Quad::Leaf(cells[y * side + x]));
+        }
+
+        let half = 1 << (level - 1);
+        let children = [(0, 0), (half, 0), (0, half), (half, half)]
+            .map(|(dx, dy)| self.intern_cells(cells, side, x + dx, y + dy, level - 1));
+
+        self.join(children)
+    }
+
+    /// Writes the cells of `id` into `cells`, with its top-left corner at
+    /// `(x, y)`
+    fn write_cells(&self, id: QuadId, cells: &mut [NodeId], side: usize, x: usize, y: usize) {
+        match self.quads[id.0] {
+            Quad::Leaf(state) => cells[y * side + x] = state,
+            Quad::Branch { level, children } => {
+                let half = 1 << (level - 1);
+                for (child, (dx, dy)) in
+                    children
+                        .into_iter()
+                        .zip([(0, 0), (half, 0), (0, half), (half, half)])
+                {
+                    self.write_cells(child, cells, side, x + dx, y + dy);
+                }
+            }
+        }
+    }
+
+    /// Whether every cell out of the background lies within the root's
+    /// central half
+    fn is_root_centered(&mut self) -> bool {
+        let [nw, ne, sw, se] = self.children(self.root);
+        let [nw, ne, sw, se] = [nw, ne, sw, se].map(|child| self.children(child));
+        let border = [
+            nw[0], nw[1], nw[2], ne[0], ne[1], ne[3], sw[0], sw[2], sw[3], se[1], se[2], se[3],
+        ];
+
+        border.into_iter().all(|id| self.is_empty(id))
+    }
+
+    /// Doubles the root, keeping the current one at its center
+    fn expand(&mut self) {
+        let level = self.level(self.root);
+        let empty = self.empty(level - 1);
+        let [nw, ne, sw, se] = self.children(self.root);
+
+        let children = [
+            self.join([empty, empty, empty, nw]),
+            self.join([empty, empty, ne, empty]),
+            self.join([empty, sw, empty, empty]),
+            self.join([se, empty, empty, empty]),
+        ];
+        self.root = self.join(children);
+
+        let quarter = 1 << (level - 1);
+        self.origin = (self.origin.0 - quarter, self.origin.1 - quarter);
+    }
+
+    /// Node made of the central half of `id`
+    fn center(&mut self, id: QuadId) -> QuadId {
+        let [nw, ne, sw, se] = self.children(id);
+        let children = [
+            self.children(nw)[3],
+            self.children(ne)[2],
+            self.children(sw)[1],
+            self.children(se)[0],
+        ];
+
+        self.join(children)
+    }
+
+    /// The 9 overlapping nodes, half as wide as `id`, tiling it in steps of a
+    /// quarter of its width
+    fn sub_quads(&mut self, id: QuadId) -> [QuadId; 9] {
+        let [nw, ne, sw, se] = self.children(id);
+        let [nw, ne, sw, se] = [nw, ne, sw, se].map(|child| self.children(child));
+
+        [
+            self.join(nw),
+            self.join([nw[1], ne[0], nw[3], ne[2]]),
+            self.join(ne),
+            self.join([nw[2], nw[3], sw[0], sw[1]]),
+            self.join([nw[3], ne[2], sw[1], se[0]]),
+            self.join([ne[2], ne[3], se[0], se[1]]),
+            self.join(sw),
+            self.join([sw[1], se[0], sw[3], se[2]]),
+            self.join(se),
+        ]
+    }
+
+    /// Central half of `id` after `2^j` generations. Needs
+    /// `j <= level - base_level`.
+    fn successor(&mut self, id: QuadId, j: u32) -> QuadId {
+        let level = self.level(id);
+        if self.is_empty(id) {
+            return self.empty(level - 1);
+        }
+        if level == self.base_level() {
+            return self.step_base(id);
+        }
+        let key = (id, j);
+        if let Some(result) = self.successors.get(&key) {
+            return *result;
+        }
+
+        // At full speed, both halves of the recursion step 2^(j - 1)
+        // generations. Otherwise the first half only recenters the nodes.
+        let is_full_speed = j == level - self.base_level();
+        let sub_quads = self.sub_quads(id).map(|sub_quad| {
+            if is_full_speed {
+                self.successor(sub_quad, j - 1)
+            } else {
+                self.center(sub_quad)
+            }
+        });
+
+        let j = if is_full_speed { j - 1 } else { j };
+        let result = [[0, 1, 3, 4], [1, 2, 4, 5], [3, 4, 6, 7], [4, 5, 7, 8]].map(|quad| {
+            let joined = self.join(quad.map(|idx| sub_quads[idx]));
+            self.successor(joined, j)
+        });
+        let result = self.join(result);
+
+        self.successors.insert(key, result);
+        result
+    }
+
+    /// Steps the center of a node at the base level a single generation, by
+    /// brute force
+    fn step_base(&mut self, id: QuadId) -> QuadId {
+        let key = (id, 0);
+        if let Some(result) = self.successors.get(&key) {
+            return *result;
+        }
+
+        let level = self.level(id);
+        let side = 1 << level;
+        let mut cells = vec![self.background; side * side];
+        self.write_cells(id, &mut cells, side, 0, 0);
+
+        let quarter = side / 4;
+        let half = side / 2;
+        let mut state_map = StateMap::with_states(self.model.n_states());
+        let mut next_cells = vec![self.background; half * half];
+        for (idx, next_cell) in next_cells.iter_mut().enumerate() {
+            let x = (quarter + idx % half) as isize;
+            let y = (quarter + idx / half) as isize;
+
+            let neighbors = self
+                .strategy
+                .planar_offsets(x, y)
+                .map(|(dx, dy, _)| cells[(y + dy) as usize * side + (x + dx) as usize]);
+            state_map.count_states(neighbors);
+            *next_cell = self.next_state(cells[y as usize * side + x as usize], &state_map);
+        }
+
+        let result = self.intern_cells(&next_cells, half, 0, 0, level - 1);
+        self.successors.insert(key, result);
+
+        result
+    }
+
+    #[inline]
+    fn next_state(&self, curr_state: NodeId, state_map: &StateMap) -> NodeId {
+        // Cells of an unbounded grid have no index, and there are no other
+        // layers to look at
+        self.model
+            .next_state(curr_state, &CellContext::new(0, state_map, &[]))
+    }
+
+    fn visit_bounds(&self, id: QuadId, (x, y): (i64, i64), bounds: &mut Option<Bounds>) {
+        match self.quads[id.0] {
+            Quad::Leaf(state) if state == self.background => {}
+            Quad::Leaf(_) => {
+                let ((min_x, min_y), (max_x, max_y)) = bounds.get_or_insert(((x, y), (x, y)));
+                *min_x = (*min_x).min(x);
+                *min_y = (*min_y).min(y);
+                *max_x = (*max_x).max(x);
+                *max_y = (*max_y).max(y);
+            }
+            Quad::Branch { level, .. } if self.empty.get(level as usize) == Some(&id) => {}
+            Quad::Branch { level, children } => {
+                let half = 1 << (level - 1);
+                for (child, (dx, dy)) in
+                    children
+                        .into_iter()
+                        .zip([(0, 0), (half, 0), (0, half), (half, half)])
+                {
+                    self.visit_bounds(child, (x + dx, y + dy), bounds);
+                }
+            }
+        }
+    }
+
+    fn count_population(
+        &self,
+        id: QuadId,
+        counts: &mut HashMap<QuadId, BTreeMap<NodeId, usize>>,
+    ) -> BTreeMap<NodeId, usize> {
+        if let Some(population) = counts.get(&id) {
+            return population.clone();
+        }
+
+        let population = match self.quads[id.0] {
+            Quad::Leaf(state) if state == self.background => BTreeMap::new(),
+            Quad::Leaf(state) => BTreeMap::from([(state, 1)]),
+            Quad::Branch { children, .. } => {
+                let mut population = BTreeMap::new();
+                for child in children {
+                    for (state, count) in self.count_population(child, counts) {
+                        *population.entry(state).or_default() += count;
+                    }
+                }
+                population
+            }
+        };
+
+        counts.insert(id, population.clone());
+        population
+    }
+}
+
+impl Stepper for HashLife {
+    #[inline]
+    fn step(&mut self) {
+        self.step_by(1);
+    }
+
+    #[inline]
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline]
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    /// How many cells are in each state, leaving the background out
+    fn population(&self) -> BTreeMap<NodeId, usize> {
+        self.count_population(self.root, &mut HashMap::new())
+    }
+
+    #[inline]
+    fn advance(&mut self, steps: u64) {
+        self.step_by(steps);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::{sparse::SparseGrid, test_utils::game_of_life_grid},
+        layer::LayerId,
+        model::{Condition, Edge, Operand, Value},
+        simulation::SparseSimulationContext,
+    };
+    use rstest::rstest;
+
+    const GLIDER: &str = include_str!("../fixtures/gol/glider.txt");
+
+    #[test]
+    fn glider_should_travel_billions_of_generations() {
+        let grid = game_of_life_grid(GLIDER);
+        let mut hashlife = HashLife::from_grid(Model::game_of_life(), &grid, NodeId(0)).unwrap();
+        let initial_shape = hashlife.to_grid().unwrap();
+
+        hashlife.advance(1_000_000_000);
+
+        // A glider moves a cell diagonally every 4 generations
+        let offset = 250_000_000;
+        assert_eq!(hashlife.generation(), 1_000_000_000);
+        assert_eq!(
+            hashlife.bounds(),
+            Some(((offset, offset), (offset + 2, offset + 2)))
+        );
+        assert_eq!(hashlife.to_grid().unwrap().cells(), initial_shape.cells());
+        assert_eq!(hashlife.population()[&NodeId(1)], 5);
+    }
+
+    #[test]
+    fn hashlife_should_match_the_sparse_grid() {
+        let grid = game_of_life_grid(
+            "
+            ░█░░█░░░
+            ██░█░░█░
+            ░░███░░█
+            █░░█░██░
+            ░█░░░█░░
+            ░░██░░░█
+            █░░░█░█░
+            ░██░░░░░
+        ",
+        );
+
+        let mut hashlife = HashLife::from_grid(Model::game_of_life(), &grid, NodeId(0)).unwrap();
+        let sparse = SparseGrid::from_grid(&grid, NodeId(0)).unwrap();
+        let mut sparse = SparseSimulationContext::new(Model::game_of_life(), sparse).unwrap();
+
+        // Odd counts go through every power of two up to them
+        for steps in [1, 3, 7, 13] {
+            hashlife.advance(steps);
+            sparse.advance(steps);
+
+            assert_eq!(hashlife.generation(), sparse.generation());
+            assert_eq!(
+                hashlife.bounds().map(|((x0, y0), (x1, y1))| (
+                    (x0 as isize, y0 as isize),
+                    (x1 as isize, y1 as isize)
+                )),
+                sparse.grid().bounds()
+            );
+            assert_eq!(
+                hashlife.to_grid().unwrap().cells(),
+                sparse.grid().to_grid().unwrap().cells()
+            );
+        }
+    }
+
+    #[test]
+    fn hashlife_should_reject_position_dependent_neighborhoods() {
+        let mut grid = game_of_life_grid(GLIDER);
+        grid.set_strategy(NeighboringStrategy::Hexagon(Default::default()));
+
+        assert!(HashLife::from_grid(Model::game_of_life(), &grid, NodeId(0)).is_err());
+    }
+
+    #[rstest]
+    #[case(Value::LayerState(LayerId(1)))]
+    #[case(Value::LayerPopulationCount(LayerId(1), NodeId(1)))]
+    fn hashlife_should_reject_models_reading_other_layers(#[case] on_when: Value) {
+        let mut model = Model::game_of_life();
+        let mut follow = Edge::new("Follow".to_string(), NodeId(0), NodeId(1));
+        follow.add_condition(Condition {
+            left: on_when,
+            operand: Operand::Equal,
+            right: Value::Absolute(1),
+        });
+        model.add_edge(follow);
+
+        let grid = game_of_life_grid(GLIDER);
+        assert!(HashLife::from_grid(model, &grid, NodeId(0)).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{BufRead, Seek};
+
+use anyhow::ensure;
+use image::{imageops::FilterType, DynamicImage, ImageReader};
+
+use crate::{
+    grid::{neighbor_strategy::NeighboringStrategy, Grid},
+    model::{Model, NodeId, Rgb},
+};
+
+/// How the colour of a pixel picks the state of its cell
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PixelMapping {
+    /// Pixels must have the exact colour of some state
+    ExactColor,
+    /// Pixels take the state whose colour is closest to theirs
+    NearestColor,
+    /// Pixels take the state of the first `(bound, state)` pair whose bound
+    /// their brightness doesn't exceed, so bounds should be increasing
+    Grayscale { thresholds: Vec<(u8, NodeId)> },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ImageImport {
+    pub mapping: PixelMapping,
+    /// Size of the grid, in cells, when it shouldn't be one cell per pixel.
+    /// Images are rescaled by picking the nearest pixel, which keeps their
+    /// colours untouched.
+    pub size: Option<(u32, u32)>,
+    /// State of the cells whose pixel matched no state
+    pub unmatched_state: NodeId,
+}
+
+/// Pixel whose colour matched no state, in grid coordinates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnmatchedPixel {
+    pub x: usize,
+    pub y: usize,
+    pub color: Rgb,
+}
+
+pub struct ImportedGrid {
+    pub grid: Grid,
+    pub unmatched: Vec<UnmatchedPixel>,
+}
+
+impl ImageImport {
+    pub fn new(mapping: PixelMapping) -> Self {
+        Self {
+            mapping,
+            size: None,
+            unmatched_state: NodeId::default(),
+        }
+    }
+
+    /// Builds a planar grid out of the PNG or BMP image read from `reader`,
+    /// where each pixel becomes a cell in a state of `model`
+    pub fn read(
+        &self,
+        reader: impl BufRead + Seek,
+        model: &Model,
+        strategy: NeighboringStrategy,
+    ) -> anyhow::Result<ImportedGrid> {
+        let image = ImageReader::new(reader).with_guessed_format()?.decode()?;
+        self.convert(image, model, strategy)
+    }
+
+    fn convert(
+        &self,
+        mut image: DynamicImage,
+        model: &Model,
+        strategy: NeighboringStrategy,
+    ) -> anyhow::Result<ImportedGrid> {
+        if let Some((width, height)) = self.size {
+            ensure!(
+                width > 0 && height > 0,
+                "grids must be at least 1 cell wide and high, got {width}x{height}"
+            );
+            image = image.resize_exact(width, height, FilterType::Nearest);
+        }
+        ensure!(
+            image.width() > 0 && image.height() > 0,
+            "the image has no pixels"
+        );
+
+        let palette = model.palette();
+        let pixels = image.to_rgb8();
+        let lumas = image.to_luma8();
+        let width = image.width() as usize;
+
+        let mut grid = Grid::empty(width * image.height() as usize, width, strategy);
+        let mut unmatched = Vec::new();
+        for (idx, (pixel, luma)) in pixels.pixels().zip(lumas.pixels()).enumerate() {
+            let color = Rgb(pixel[0], pixel[1], pixel[2]);
+            let state = match &self.mapping {
+                PixelMapping::ExactColor => palette
+                    .iter()
+                    .position(|state_color| *state_color == color)
+                    .map(NodeId::from_index),
+                PixelMapping::NearestColor => palette
+                    .iter()
+                    .enumerate()
+                    .min_by_key(|(_, state_color)| distance(**state_color, color))
+                    .map(|(idx, _)| NodeId::from_index(idx)),
+                PixelMapping::Grayscale { thresholds } => thresholds
+                    .iter()
+                    .find(|(bound, _)| luma[0] <= *bound)
+                    .map(|(_, state)| *state),
+            };
+
+            grid.set_cell_at(idx, state.unwrap_or(self.unmatched_state));
+            if state.is_none() {
+                unmatched.push(UnmatchedPixel {
+                    x: idx % width,
+                    y: idx / width,
+                    color,
+                });
+            }
+        }
+
+        Ok(ImportedGrid { grid, unmatched })
+    }
+}
+
+/// Squared euclidean distance between two colours
+fn distance(a: Rgb, b: Rgb) -> u32 {
+    [(a.0, b.0), (a.1, b.1), (a.2, b.2)]
+        .into_iter()
+        .map(|(a, b)| (a.abs_diff(b) as u32).pow(2))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageFormat, RgbImage};
+
+    use super::*;
+    use crate::grid::test_utils::to_game_of_life_output;
+
+    /// 3x2 image, with a white pixel, a near-white one and a red one
+    fn encode(format: ImageFormat) -> Vec<u8> {
+        let mut image = RgbImage::new(3, 2);
+        image.put_pixel(1, 0, [255, 255, 255].into());
+        image.put_pixel(2, 0, [250, 240, 245].into());
+        image.put_pixel(0, 1, [200, 0, 0].into());
+
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), format)
+            .unwrap();
+        bytes
+    }
+
+    fn import(import: &ImageImport, format: ImageFormat) -> ImportedGrid {
+        import
+            .read(
+                Cursor::new(encode(format)),
+                &Model::game_of_life(),
+                NeighboringStrategy::SquareAndCorners,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn exact_colors_should_report_unmatched_pixels() {
+        let imported = import(
+            &ImageImport::new(PixelMapping::ExactColor),
+            ImageFormat::Png,
+        );
+
+        assert_eq!(to_game_of_life_output(&imported.grid), "░█░\n░░░");
+        assert_eq!(
+            imported.unmatched,
+            [
+                UnmatchedPixel {
+                    x: 2,
+                    y: 0,
+                    color: Rgb(250, 240, 245)
+                },
+                UnmatchedPixel {
+                    x: 0,
+                    y: 1,
+                    color: Rgb(200, 0, 0)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn nearest_colors_should_match_every_pixel() {
+        let imported = import(
+            &ImageImport::new(PixelMapping::NearestColor),
+            ImageFormat::Bmp,
+        );
+
+        assert_eq!(to_game_of_life_output(&imported.grid), "░██\n░░░");
+        assert!(imported.unmatched.is_empty());
+    }
+
+    #[test]
+    fn grayscale_thresholds_should_pick_states_by_brightness() {
+        let mut options = ImageImport::new(PixelMapping::Grayscale {
+            thresholds: vec![(40, NodeId(1)), (200, NodeId(0))],
+        });
+        options.size = Some((6, 2));
+
+        let imported = import(&options, ImageFormat::Png);
+
+        assert_eq!(imported.grid.extents(), [6, 2, 1]);
+        assert_eq!(to_game_of_life_output(&imported.grid), "██░░░░\n░░████");
+        assert_eq!(imported.unmatched.len(), 4);
+    }
+}
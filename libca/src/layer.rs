@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::Grid,
+    model::{Model, NodeId},
+    state_map::StateMap,
+};
+
+pub const DEFAULT_LAYER_NAME: &str = "Main";
+
+#[derive(
+    Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default,
+)]
+pub struct LayerId(pub(crate) usize);
+
+impl LayerId {
+    #[inline]
+    pub fn from_index(idx: usize) -> Self {
+        Self(idx)
+    }
+
+    #[inline]
+    pub fn as_index(self) -> usize {
+        self.0
+    }
+}
+
+/// A named field living on the shared lattice, stepped by its own [`Model`]
+pub struct Layer {
+    pub(crate) name: String,
+    pub model: Model,
+    pub grid: Grid,
+}
+
+impl Layer {
+    pub fn new(name: String, model: Model, grid: Grid) -> Self {
+        Self { name, model, grid }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Everything a [`crate::Condition`] can look at while a single cell is
+/// being evaluated.
+///
+/// Layers are always read from the previous generation, so every layer
+/// steps synchronously regardless of the order they're evaluated in.
+pub struct CellContext<'c> {
+    pub(crate) idx: usize,
+    pub(crate) neighbors: &'c StateMap,
+    pub(crate) layers: &'c [Layer],
+}
+
+impl<'c> CellContext<'c> {
+    pub fn new(idx: usize, neighbors: &'c StateMap, layers: &'c [Layer]) -> Self {
+        Self {
+            idx,
+            neighbors,
+            layers,
+        }
+    }
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.idx
+    }
+
+    #[inline]
+    pub fn neighbors(&self) -> &StateMap {
+        self.neighbors
+    }
+
+    /// Counts how many of this cell's neighbors are in `state` on another
+    /// layer. Unknown layers have no neighbors at all.
+    pub fn layer_population(&self, layer: LayerId, state: NodeId) -> u32 {
+        self.layers
+            .get(layer.as_index())
+            .map(|layer| {
+                layer
+                    .grid
+                    .iter_neighbors(self.idx)
+                    .filter(|neighbor| *neighbor == state)
+                    .count() as u32
+            })
+            .unwrap_or_default()
+    }
+
+    /// State of this very cell on another layer
+    pub fn layer_state(&self, layer: LayerId) -> Option<NodeId> {
+        self.layers
+            .get(layer.as_index())
+            .and_then(|layer| layer.grid.cells().get(self.idx))
+            .copied()
+    }
+}
@@ -1,14 +1,21 @@
 #![feature(iter_collect_into)]
-#![feature(vec_into_raw_parts)]
 
+pub mod export;
 pub mod grid;
+pub mod hashlife;
+pub mod import;
+pub mod layer;
 pub mod model;
 pub mod simulation;
 pub mod state_map;
+pub mod update_scheme;
 
 use std::{num::NonZero, sync::LazyLock};
 
-pub use model::{Condition, Edge, Model, Node, NodeId, Operand, Value};
+pub use layer::{Layer, LayerId};
+pub use model::{
+    BlockRule, Condition, Edge, EdgeId, Model, Movement, MovementKind, Node, NodeId, Operand, Value,
+};
 
 pub static AVAILABLE_PARALLELISM: LazyLock<usize> = LazyLock::new(|| {
     std::thread::available_parallelism()
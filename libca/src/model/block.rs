@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+
+use super::node::NodeId;
+
+/// Number of cells in a Margolus block
+pub const BLOCK_SIZE: usize = 4;
+
+/// Cells of a 2×2 block in reading order: top-left, top-right, bottom-left
+/// and bottom-right
+pub type Block = [NodeId; BLOCK_SIZE];
+
+/// Rewrites a whole 2×2 block at once. Blocks with no matching rule are
+/// left untouched.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct BlockRule {
+    pub(crate) name: String,
+    pub(crate) from: Block,
+    pub(crate) to: Block,
+}
+
+impl BlockRule {
+    pub fn new(name: String, from: Block, to: Block) -> Self {
+        Self { name, from, to }
+    }
+
+    #[inline]
+    pub fn transition(&self, block: &Block) -> Option<Block> {
+        (&self.from == block).then_some(self.to)
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn from_block(&self) -> &Block {
+        &self.from
+    }
+
+    #[inline]
+    pub fn to_block(&self) -> &Block {
+        &self.to
+    }
+}
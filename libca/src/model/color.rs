@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+use super::NodeId;
+
+/// Colour of the cells in some state, as red, green and blue
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Rgb(pub u8, pub u8, pub u8);
+
+/// Colours for states that weren't given one. The first two match the usual
+/// dead and alive cells, the others are easy to tell apart.
+const DEFAULT_PALETTE: [Rgb; 10] = [
+    Rgb(0, 0, 0),
+    Rgb(255, 255, 255),
+    Rgb(230, 25, 75),
+    Rgb(60, 180, 75),
+    Rgb(0, 130, 200),
+    Rgb(255, 225, 25),
+    Rgb(245, 130, 48),
+    Rgb(145, 30, 180),
+    Rgb(70, 240, 240),
+    Rgb(128, 128, 128),
+];
+
+impl Rgb {
+    /// Default colour of `state`, going around the palette for large states
+    pub fn default_for(state: NodeId) -> Self {
+        DEFAULT_PALETTE[state.as_index() % DEFAULT_PALETTE.len()]
+    }
+}
@@ -1,11 +1,11 @@
 use serde::{Deserialize, Serialize};
 use strum::IntoStaticStr;
 
-use crate::state_map::StateMap;
+use crate::layer::{CellContext, LayerId};
 
 use super::node::NodeId;
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
 pub struct EdgeId(pub(crate) u32);
 
 #[derive(Serialize, Deserialize)]
@@ -40,13 +40,14 @@ impl Edge {
         }
     }
 
-    pub fn transition(&self, node_id: NodeId, neighbors: &StateMap) -> Option<NodeId> {
-        (self.from_node == node_id
-            && self
-                .conditions
-                .iter()
-                .all(|cond| cond.is_satisfied(neighbors)))
-        .then_some(self.to_node)
+    pub fn transition(&self, node_id: NodeId, cell: &CellContext) -> Option<NodeId> {
+        (self.from_node == node_id && self.conditions.iter().all(|cond| cond.is_satisfied(cell)))
+            .then_some(self.to_node)
+    }
+
+    #[inline]
+    pub fn id(&self) -> &EdgeId {
+        &self.id
     }
 
     #[inline]
@@ -69,7 +70,7 @@ impl Edge {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub struct Condition {
     pub(crate) left: Value,
     pub operand: Operand,
@@ -77,9 +78,9 @@ pub struct Condition {
 }
 
 impl Condition {
-    fn is_satisfied(&self, neighbors: &StateMap) -> bool {
-        let left = self.left.to_absolute(neighbors);
-        let right = self.right.to_absolute(neighbors);
+    pub(crate) fn is_satisfied(&self, cell: &CellContext) -> bool {
+        let left = self.left.to_absolute(cell);
+        let right = self.right.to_absolute(cell);
         self.operand.evaluate(left, right)
     }
 
@@ -94,23 +95,32 @@ impl Condition {
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Value {
     Absolute(u32),
     PopulationCount(NodeId),
+    /// Neighbors in the given state, counted on another layer
+    LayerPopulationCount(LayerId, NodeId),
+    /// Index of the state the same cell has on another layer
+    LayerState(LayerId),
 }
 
 impl Value {
     #[inline]
-    fn to_absolute(self, neighbors: &StateMap) -> u32 {
+    pub(crate) fn to_absolute(self, cell: &CellContext) -> u32 {
         match self {
             Value::Absolute(abs) => abs,
-            Value::PopulationCount(node_id) => neighbors.get_count(node_id) as u32,
+            Value::PopulationCount(node_id) => cell.neighbors().get_count(node_id),
+            Value::LayerPopulationCount(layer, node_id) => cell.layer_population(layer, node_id),
+            Value::LayerState(layer) => cell
+                .layer_state(layer)
+                .map(|node_id| node_id.as_index() as u32)
+                .unwrap_or_default(),
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, IntoStaticStr)]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, IntoStaticStr)]
 pub enum Operand {
     /// `==`
     #[strum(serialize = "=")]
@@ -144,4 +154,16 @@ impl Operand {
             Operand::Different => left != right,
         }
     }
+
+    /// Operand that evaluates to the opposite result
+    pub const fn negated(self) -> Self {
+        match self {
+            Operand::Equal => Operand::Different,
+            Operand::Greater => Operand::LessOrEqual,
+            Operand::GreaterOrEqual => Operand::Less,
+            Operand::Less => Operand::GreaterOrEqual,
+            Operand::LessOrEqual => Operand::Greater,
+            Operand::Different => Operand::Equal,
+        }
+    }
 }
@@ -1,22 +1,40 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
-use edge::EdgeId;
-pub use edge::{Condition, Edge, Operand, Value};
+pub use block::{Block, BlockRule, BLOCK_SIZE};
+pub use color::Rgb;
+pub use edge::{Condition, Edge, EdgeId, Operand, Value};
+pub use movement::{Movement, MovementKind};
 pub use node::Node;
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
 pub use node::NodeId;
 
-use crate::state_map::StateMap;
+use crate::{grid::neighbor_strategy::NeighboringStrategy, layer::CellContext};
 
+mod block;
+mod color;
 mod edge;
+mod movement;
 mod node;
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct Model {
     pub(crate) nodes: BTreeMap<NodeId, Node>,
     pub(crate) edges: Vec<Edge>,
+    /// When present, the model is stepped on a Margolus partition instead
+    /// of cell by cell, and `edges` are ignored
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) block_rules: Vec<BlockRule>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) movements: Vec<Movement>,
+    /// Neighborhood the model was designed for, replacing the grid's own
+    /// strategy when present
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) neighborhood: Option<NeighboringStrategy>,
+    /// Colours given to states, the others get [`Rgb::default_for`]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub(crate) colors: BTreeMap<NodeId, Rgb>,
 }
 
 impl Model {
@@ -24,11 +42,64 @@ impl Model {
         Self::default()
     }
 
-    pub fn next_state(&self, curr_state: NodeId, neighbors: &StateMap) -> NodeId {
+    pub fn next_state(&self, curr_state: NodeId, cell: &CellContext) -> NodeId {
+        self.fired_edge(curr_state, cell)
+            .map_or(curr_state, |idx| self.edges[idx].to_node)
+    }
+
+    /// Index in [`Model::all_edges`] of the edge that takes the cell out of
+    /// `curr_state`, if any
+    pub(crate) fn fired_edge(&self, curr_state: NodeId, cell: &CellContext) -> Option<usize> {
+        self.edges
+            .iter()
+            .position(|edge| edge.transition(curr_state, cell).is_some())
+    }
+
+    pub fn next_block(&self, block: &Block) -> Block {
+        self.block_rules
+            .iter()
+            .find_map(|rule| rule.transition(block))
+            .unwrap_or(*block)
+    }
+
+    #[inline]
+    pub fn is_block_model(&self) -> bool {
+        !self.block_rules.is_empty()
+    }
+
+    /// Number of states cells may be in, counting from the first one up to
+    /// the last one still in the model
+    pub fn n_states(&self) -> usize {
+        self.nodes
+            .keys()
+            .next_back()
+            .map_or(0, |id| id.as_index() + 1)
+    }
+
+    /// Whether the next state of a cell only depends on its current state
+    /// and on how many of its neighbors are in each state, on its own layer
+    pub fn is_outer_totalistic(&self) -> bool {
+        !self.is_block_model()
+            && self.movements.is_empty()
+            && self
+                .edges
+                .iter()
+                .flat_map(|edge| edge.conditions())
+                .flat_map(|cond| [cond.left(), cond.right()])
+                .all(|value| matches!(value, Value::Absolute(_) | Value::PopulationCount(_)))
+    }
+
+    /// States whose neighbor count some transition looks at
+    pub fn counted_states(&self) -> BTreeSet<NodeId> {
         self.edges
             .iter()
-            .find_map(|edge| edge.transition(curr_state, neighbors))
-            .unwrap_or(curr_state)
+            .flat_map(|edge| edge.conditions())
+            .flat_map(|cond| [cond.left(), cond.right()])
+            .filter_map(|value| match value {
+                Value::PopulationCount(state) => Some(*state),
+                _ => None,
+            })
+            .collect()
     }
 
     pub fn nodes(&self) -> impl ExactSizeIterator<Item = (&NodeId, &Node)> {
@@ -39,6 +110,25 @@ impl Model {
         self.nodes.get(id)
     }
 
+    #[inline]
+    pub fn color(&self, state: NodeId) -> Rgb {
+        self.colors
+            .get(&state)
+            .copied()
+            .unwrap_or_else(|| Rgb::default_for(state))
+    }
+
+    pub fn set_color(&mut self, state: NodeId, color: Rgb) {
+        self.colors.insert(state, color);
+    }
+
+    /// Colour of every state, indexed by [`NodeId::as_index`]
+    pub fn palette(&self) -> Vec<Rgb> {
+        (0..self.n_states())
+            .map(|idx| self.color(NodeId::from_index(idx)))
+            .collect()
+    }
+
     pub fn all_edges(&self) -> &[Edge] {
         &self.edges
     }
@@ -52,6 +142,31 @@ impl Model {
             .filter(move |edge| &edge.from_node == from_node_id)
     }
 
+    pub fn block_rules(&self) -> &[BlockRule] {
+        &self.block_rules
+    }
+
+    pub fn add_block_rule(&mut self, rule: BlockRule) {
+        self.block_rules.push(rule);
+    }
+
+    #[inline]
+    pub fn neighborhood(&self) -> Option<&NeighboringStrategy> {
+        self.neighborhood.as_ref()
+    }
+
+    pub fn set_neighborhood(&mut self, neighborhood: Option<NeighboringStrategy>) {
+        self.neighborhood = neighborhood;
+    }
+
+    pub fn movements(&self) -> &[Movement] {
+        &self.movements
+    }
+
+    pub fn add_movement(&mut self, movement: Movement) {
+        self.movements.push(movement);
+    }
+
     pub fn add_node(&mut self, node: Node) {
         let next_node_id = self
             .nodes
@@ -87,6 +202,8 @@ impl Model {
 
         self.edges
             .retain(|edge| edge.from_node != node_id && edge.to_node != node_id);
+        self.block_rules
+            .retain(|rule| !rule.from.contains(&node_id) && !rule.to.contains(&node_id));
 
         let Some((highest_node_id, _)) = self.nodes.last_key_value() else {
             return;
@@ -110,6 +227,25 @@ impl Model {
             }
         });
 
+        self.movements.iter_mut().for_each(|movement| {
+            let leave = match &mut movement.kind {
+                MovementKind::Swap => None,
+                MovementKind::Move { leave } => Some(leave),
+            };
+
+            [&mut movement.from_node, &mut movement.into_node]
+                .into_iter()
+                .chain(leave)
+                .filter(|movement_node| **movement_node == highest_node_id)
+                .for_each(|movement_node| *movement_node = node_id);
+        });
+
+        self.block_rules
+            .iter_mut()
+            .flat_map(|rule| rule.from.iter_mut().chain(rule.to.iter_mut()))
+            .filter(|block_node| **block_node == highest_node_id)
+            .for_each(|block_node| *block_node = node_id);
+
         if let Some(highest_node) = self.nodes.remove(&highest_node_id) {
             self.nodes.insert(node_id, highest_node);
         }
@@ -167,8 +303,71 @@ impl Model {
                     }],
                 },
             ],
+            block_rules: Vec::new(),
+            movements: Vec::new(),
+            neighborhood: None,
+            colors: BTreeMap::new(),
         }
     }
+
+    /// Grains of sand fall straight down, or slide diagonally when blocked
+    pub fn falling_sand() -> Self {
+        let empty = NodeId(0);
+        let sand = NodeId(1);
+
+        let mut model = Self::new();
+        model.add_node(Node("Empty".to_string()));
+        model.add_node(Node("Sand".to_string()));
+        model.add_movement(Movement::new(
+            "Fall".to_string(),
+            sand,
+            empty,
+            vec![(0, 1), (-1, 1), (1, 1)],
+            MovementKind::Swap,
+        ));
+
+        model
+    }
+
+    /// Fredkin and Margolus' Billiard Ball Machine: balls travel diagonally
+    /// and bounce off each other when colliding head-on
+    pub fn billiard_ball_machine() -> Self {
+        let empty = NodeId(0);
+        let ball = NodeId(1);
+
+        let mut model = Self::new();
+        model.add_node(Node("Empty".to_string()));
+        model.add_node(Node("Ball".to_string()));
+
+        let corners = [
+            ("Top-left", [ball, empty, empty, empty]),
+            ("Top-right", [empty, ball, empty, empty]),
+            ("Bottom-left", [empty, empty, ball, empty]),
+            ("Bottom-right", [empty, empty, empty, ball]),
+        ];
+
+        // A lone ball crosses the block to its opposite corner
+        corners
+            .iter()
+            .zip(corners.iter().rev())
+            .for_each(|((name, from), (_, to))| {
+                model.add_block_rule(BlockRule::new(format!("{name} moves"), *from, *to));
+            });
+
+        // Head-on collisions bounce off perpendicularly
+        model.add_block_rule(BlockRule::new(
+            "Main diagonal collision".to_string(),
+            [ball, empty, empty, ball],
+            [empty, ball, ball, empty],
+        ));
+        model.add_block_rule(BlockRule::new(
+            "Anti-diagonal collision".to_string(),
+            [empty, ball, ball, empty],
+            [ball, empty, empty, ball],
+        ));
+
+        model
+    }
 }
 
 #[cfg(test)]
@@ -181,4 +380,45 @@ mod tests {
             insta::assert_ron_snapshot!(Model::game_of_life());
         });
     }
+
+    #[test]
+    fn block_model_serialization() {
+        insta::with_settings!({sort_maps =>true}, {
+            insta::assert_ron_snapshot!(Model::billiard_ball_machine());
+        });
+    }
+
+    #[test]
+    fn custom_neighborhood_should_load_from_model_file() {
+        let serialized = r#"Model(
+            nodes: {
+                NodeId(0): Node("Dead"),
+                NodeId(1): Node("Alive"),
+            },
+            edges: [],
+            neighborhood: Some(Custom([(0, -2), (0, 2), (-2, 0), (2, 0)])),
+        )"#;
+
+        let model: Model = ron::from_str(serialized).unwrap();
+
+        assert_eq!(
+            model.neighborhood(),
+            Some(&NeighboringStrategy::Custom(vec![
+                (0, -2),
+                (0, 2),
+                (-2, 0),
+                (2, 0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn block_rules_survive_round_trip() {
+        let model = Model::billiard_ball_machine();
+
+        let serialized = ron::to_string(&model).unwrap();
+        let deserialized: Model = ron::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized.block_rules(), model.block_rules());
+    }
 }
@@ -0,0 +1,108 @@
+use serde::{Deserialize, Serialize};
+
+use crate::layer::CellContext;
+
+use super::{edge::Condition, node::NodeId};
+
+/// Carries a cell's state into one of its neighbors, for particle-like
+/// models such as falling sand, lattice gases or traffic.
+///
+/// Movements are resolved after the regular transitions of a step. A cell
+/// in `from_node` whose conditions hold picks the first of its `offsets`
+/// whose destination is in `into_node`.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Movement {
+    pub(crate) name: String,
+    pub(crate) from_node: NodeId,
+    pub(crate) into_node: NodeId,
+    /// Candidate destinations as `(dx, dy)`, by order of preference
+    pub(crate) offsets: Vec<(isize, isize)>,
+    pub(crate) kind: MovementKind,
+    /// Conditions must ALL match, so there's an implicit `&&`
+    /// operator between any pair of conditions.
+    pub(crate) conditions: Vec<Condition>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MovementKind {
+    /// Source and destination exchange their states
+    Swap,
+    /// Destination takes the source's state, and the source is left behind
+    /// in the given state
+    Move { leave: NodeId },
+}
+
+impl Movement {
+    pub fn new(
+        name: String,
+        from_node: NodeId,
+        into_node: NodeId,
+        offsets: Vec<(isize, isize)>,
+        kind: MovementKind,
+    ) -> Self {
+        Self {
+            name,
+            from_node,
+            into_node,
+            offsets,
+            kind,
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn add_condition(&mut self, cond: Condition) {
+        self.conditions.push(cond);
+    }
+
+    /// Whether a cell in `node_id` attempts this movement at all
+    pub fn is_triggered(&self, node_id: NodeId, cell: &CellContext) -> bool {
+        self.from_node == node_id && self.conditions.iter().all(|cond| cond.is_satisfied(cell))
+    }
+
+    /// States left at the source and destination once the movement happens
+    #[inline]
+    pub fn outcome(&self) -> (NodeId, NodeId) {
+        let source = match self.kind {
+            MovementKind::Swap => self.into_node,
+            MovementKind::Move { leave } => leave,
+        };
+
+        (source, self.from_node)
+    }
+
+    /// Whether applying this movement never changes how many cells are in
+    /// each state
+    #[inline]
+    pub fn is_conservative(&self) -> bool {
+        self.outcome().0 == self.into_node
+    }
+
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[inline]
+    pub fn from_node_id(&self) -> &NodeId {
+        &self.from_node
+    }
+
+    #[inline]
+    pub fn into_node_id(&self) -> &NodeId {
+        &self.into_node
+    }
+
+    #[inline]
+    pub fn offsets(&self) -> &[(isize, isize)] {
+        &self.offsets
+    }
+
+    #[inline]
+    pub fn kind(&self) -> MovementKind {
+        self.kind
+    }
+
+    pub fn conditions(&self) -> &[Condition] {
+        &self.conditions
+    }
+}
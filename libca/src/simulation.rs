@@ -1,32 +1,717 @@
-use crate::{grid::Grid, model::Model, state_map::StatePool};
+mod cycle;
+mod explain;
+mod history;
+mod snapshot;
+mod statistics;
+
+use std::{collections::BTreeMap, fmt::Display};
+
+use anyhow::ensure;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+use crate::{
+    grid::{sparse::SparseGrid, BitGrid, Grid},
+    layer::{CellContext, Layer, LayerId, DEFAULT_LAYER_NAME},
+    model::{Model, NodeId},
+    state_map::StateMap,
+    update_scheme::{CellClock, UpdateScheme},
+};
+
+pub use cycle::{Cycle, CycleDetector};
+pub use explain::{ConditionTrace, EdgeTrace, Explanation};
+pub use statistics::{GenerationStatistics, Statistics};
+
+use history::History;
+use statistics::StatisticsCollector;
 
 pub struct SimulationContext {
-    pub model: Model,
-    pub grid: Grid,
-    state_pool: StatePool,
+    layers: Vec<Layer>,
+    /// Neighbor counts for cells updated one at a time
+    scratch: StateMap,
+    generation: u64,
+    update_scheme: UpdateScheme,
+    seed: u64,
+    rng: ChaCha8Rng,
+    clocks: Vec<CellClock>,
+    conservation_check: bool,
+    history: Option<History>,
+    statistics: Option<StatisticsCollector>,
+}
+
+/// A movement changed how many cells were in some state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConservationError {
+    pub layer: LayerId,
+    pub state: NodeId,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Display for ConservationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Movements on layer #{} took state #{} from {} to {} cells",
+            self.layer.as_index(),
+            self.state.as_index(),
+            self.before,
+            self.after
+        )
+    }
+}
+
+impl std::error::Error for ConservationError {}
+
+/// Generation a layer is stepped to, in the form its grid will hold it
+enum NextGeneration {
+    Cells(Vec<NodeId>),
+    Packed(BitGrid),
+}
+
+/// Which cells of non-block layers take part in a synchronous update
+enum Selection<'s> {
+    All,
+    Only(&'s [bool]),
+    None,
 }
 
 impl SimulationContext {
-    pub fn new(model: Model, grid: Grid) -> Self {
+    pub fn new(model: Model, mut grid: Grid) -> Self {
+        let seed = rand::random();
+        if let Some(neighborhood) = model.neighborhood() {
+            grid.set_strategy(neighborhood.clone());
+        }
+
         Self {
-            state_pool: StatePool::new(),
-            model,
-            grid,
+            layers: vec![Layer::new(DEFAULT_LAYER_NAME.to_string(), model, grid)],
+            scratch: StateMap::new(),
+            generation: 0,
+            update_scheme: UpdateScheme::default(),
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            clocks: Vec::new(),
+            conservation_check: false,
+            history: None,
+            statistics: None,
+        }
+    }
+
+    /// Adds another field on the same lattice as the existing layers
+    pub fn add_layer(
+        &mut self,
+        name: String,
+        model: Model,
+        mut grid: Grid,
+    ) -> anyhow::Result<LayerId> {
+        anyhow::ensure!(
+            self.grid().same_lattice(&grid),
+            "Layer '{name}' must have the same dimensions as the existing layers"
+        );
+        anyhow::ensure!(
+            self.layer_id(&name).is_none(),
+            "There already is a layer named '{name}'"
+        );
+
+        if let Some(neighborhood) = model.neighborhood() {
+            grid.set_strategy(neighborhood.clone());
         }
+
+        self.layers.push(Layer::new(name, model, grid));
+        Ok(LayerId(self.layers.len() - 1))
     }
 
+    /// Restarts the random number generator used by the update scheme, so
+    /// that runs starting from the same state are reproducible
+    pub fn reseed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.clocks.clear();
+    }
+
+    #[inline]
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_update_scheme(&mut self, update_scheme: UpdateScheme) {
+        self.update_scheme = update_scheme;
+        self.clocks.clear();
+    }
+
+    #[inline]
+    pub fn update_scheme(&self) -> UpdateScheme {
+        self.update_scheme
+    }
+
+    /// Makes [`SimulationContext::try_step`] verify that movements never
+    /// create or destroy cells of any state. Violations are only reported by
+    /// `try_step`, never by [`SimulationContext::step`].
+    pub fn set_conservation_check(&mut self, enabled: bool) {
+        self.conservation_check = enabled;
+    }
+
+    /// Steps once, ignoring conservation violations even when
+    /// [`SimulationContext::set_conservation_check`] is on. Use
+    /// [`SimulationContext::try_step`] to learn about them.
     pub fn step(&mut self) {
+        let _ = self.try_step();
+    }
+
+    /// Steps once, returning the first conservation violation found when the
+    /// check is on. The step is taken in full all the same.
+    pub fn try_step(&mut self) -> Result<(), ConservationError> {
+        self.apply_neighborhoods();
+        let pending = self.history.take().map(|mut history| {
+            let pending = history.before_step(self);
+            self.history = Some(history);
+            pending
+        });
+        if let Some(mut collector) = self.statistics.take() {
+            collector.before_step(self);
+            self.statistics = Some(collector);
+        }
+        let n_cells = self.grid().n_cells();
+
+        match self.update_scheme {
+            UpdateScheme::Synchronous => self.step_synchronously(Selection::All),
+            UpdateScheme::RandomSequential => {
+                for _ in 0..n_cells {
+                    let idx = self.rng.random_range(0..n_cells);
+                    self.update_in_place(idx);
+                }
+                self.step_synchronously(Selection::None);
+            }
+            UpdateScheme::LineSweep => {
+                (0..n_cells).for_each(|idx| self.update_in_place(idx));
+                self.step_synchronously(Selection::None);
+            }
+            UpdateScheme::RandomIndependent { probability } => {
+                // Snapshots may hold any probability, NaN included
+                let probability = match probability {
+                    probability if probability.is_nan() => 0.0,
+                    probability => probability.clamp(0.0, 1.0),
+                };
+                let selected: Vec<_> = (0..n_cells)
+                    .map(|_| self.rng.random_bool(probability))
+                    .collect();
+                self.step_synchronously(Selection::Only(&selected));
+            }
+            UpdateScheme::Clocked {
+                min_period,
+                max_period,
+            } => {
+                if self.clocks.len() != n_cells {
+                    self.clocks = (0..n_cells)
+                        .map(|_| CellClock::random(&mut self.rng, min_period, max_period))
+                        .collect();
+                }
+
+                let selected: Vec<_> = self
+                    .clocks
+                    .iter()
+                    .map(|clock| clock.ticks_at(self.generation))
+                    .collect();
+                self.step_synchronously(Selection::Only(&selected));
+            }
+        }
+
+        let movements = self.step_movements();
+        self.generation += 1;
+
+        if let Some((mut history, pending)) = self.history.take().zip(pending) {
+            history.after_step(pending, self);
+            self.history = Some(history);
+        }
+        if let Some(mut collector) = self.statistics.take() {
+            collector.after_step(self);
+            self.statistics = Some(collector);
+        }
+
+        movements
+    }
+
+    fn step_synchronously(&mut self, selection: Selection) {
+        let mut next_generations: Vec<_> = self
+            .layers
+            .iter_mut()
+            .enumerate()
+            .map(|(layer_idx, layer)| {
+                if layer.model.is_block_model() {
+                    return Some(NextGeneration::Cells(layer.grid.take_next_cells()));
+                }
+                match selection {
+                    Selection::None => return None,
+                    // Packed layers are stepped on their own
+                    Selection::All => {
+                        let firings = self
+                            .statistics
+                            .as_mut()
+                            .filter(|_| layer_idx == 0)
+                            .map(|collector| collector.firings.as_mut_slice());
+                        if let Some(packed) = layer.grid.next_bit_packed(&layer.model, firings) {
+                            return Some(NextGeneration::Packed(packed));
+                        }
+                    }
+                    Selection::Only(_) => {}
+                }
+
+                Some(NextGeneration::Cells(layer.grid.take_next_cells()))
+            })
+            .collect();
+
+        // Every layer reads from the previous generation of all layers, only
+        // committing once all of them were evaluated
+        for (layer_idx, (layer, next)) in self
+            .layers
+            .iter()
+            .zip(next_generations.iter_mut())
+            .enumerate()
+        {
+            let Some(NextGeneration::Cells(next_cells)) = next else {
+                continue;
+            };
+            if layer.model.is_block_model() {
+                // The Margolus partition shifts by one cell on every other step
+                let offset = (self.generation % 2) as usize;
+                layer
+                    .grid
+                    .compute_next_blocks(next_cells, offset, |block| layer.model.next_block(block));
+                continue;
+            }
+
+            // Quiescent cells can only be skipped when every cell was evaluated
+            // last time, and nothing but its own layer can affect a cell
+            let skip_quiescent =
+                matches!(selection, Selection::All) && layer.model.is_outer_totalistic();
+            // Only the main layer's firings are counted. Quiescent cells keep
+            // firing the same edge, which only matters for edges looping back
+            // to their own state.
+            let firings = self.statistics.as_mut().filter(|_| layer_idx == 0);
+            let n_edges = firings
+                .as_ref()
+                .map_or(0, |_| layer.model.all_edges().len());
+            let mut loops_back = vec![false; layer.model.n_states()];
+            if firings.is_some() {
+                layer
+                    .model
+                    .all_edges()
+                    .iter()
+                    .filter(|edge| edge.from_node_id() == edge.to_node_id())
+                    .for_each(|edge| loops_back[edge.from_node_id().as_index()] = true);
+            }
+
+            let tallies = layer.grid.compute_next_tallied(
+                next_cells,
+                layer.model.n_states(),
+                |state| skip_quiescent && loops_back.get(state.as_index()) != Some(&true),
+                n_edges,
+                |idx, curr_state, state_map, tallies| {
+                    if let Selection::Only(selected) = selection {
+                        if !selected[idx] {
+                            return curr_state;
+                        }
+                    }
+
+                    let cell = CellContext::new(idx, state_map, &self.layers);
+                    let fired = layer.model.fired_edge(curr_state, &cell);
+                    fired.map_or(curr_state, |edge_idx| {
+                        if let Some(tally) = tallies.get_mut(edge_idx) {
+                            *tally += 1;
+                        }
+                        *layer.model.all_edges()[edge_idx].to_node_id()
+                    })
+                },
+            );
+            if let Some(collector) = firings {
+                collector.add_firings(&tallies);
+            }
+        }
+
+        self.layers
+            .iter_mut()
+            .zip(next_generations)
+            .filter_map(|(layer, next)| Some((layer, next?)))
+            .for_each(|(layer, next)| match next {
+                NextGeneration::Cells(next_cells) => layer.grid.commit_next(next_cells),
+                NextGeneration::Packed(packed) => layer.grid.commit_bit_packed(packed),
+            });
+
+        // Cells left out of the update never settled on their neighborhood
+        if let Selection::Only(_) = selection {
+            self.layers
+                .iter_mut()
+                .for_each(|layer| layer.grid.forget_changes());
+        }
+    }
+
+    /// Puts grids back on the neighborhood of their model, which may have
+    /// been changed through [`SimulationContext::model_mut`] or
+    /// [`SimulationContext::layer_mut`]
+    fn apply_neighborhoods(&mut self) {
+        for layer in &mut self.layers {
+            if let Some(neighborhood) = layer.model.neighborhood() {
+                if neighborhood != layer.grid.neighbor_ctx().strategy() {
+                    layer.grid.set_strategy(neighborhood.clone());
+                }
+            }
+        }
+    }
+
+    /// Resolves the movements of every layer, going through cells in reading
+    /// order. When several cells compete for the same destination, the first
+    /// one wins and the others stay put.
+    fn step_movements(&mut self) -> Result<(), ConservationError> {
+        let mut result = Ok(());
+
+        for layer_idx in 0..self.layers.len() {
+            let layer = &self.layers[layer_idx];
+            if layer.model.movements().is_empty() {
+                continue;
+            }
+
+            let claims = self.movement_claims(layer);
+            let population_before = self.conservation_check.then(|| layer.grid.population());
+
+            let layer = &mut self.layers[layer_idx];
+            let mut is_taken = vec![false; claims.len()];
+
+            for (source, claim) in claims.into_iter().enumerate() {
+                let Some((destination, movement_idx)) = claim else {
+                    continue;
+                };
+
+                if is_taken[source] || is_taken[destination] {
+                    continue;
+                }
+
+                is_taken[source] = true;
+                is_taken[destination] = true;
+
+                let (source_state, destination_state) =
+                    layer.model.movements()[movement_idx].outcome();
+                layer.grid.step_cell_at(source, source_state);
+                layer.grid.step_cell_at(destination, destination_state);
+            }
+
+            let Some(population_before) = population_before else {
+                continue;
+            };
+
+            let population_after = layer.grid.population();
+            let violation = population_before
+                .keys()
+                .chain(population_after.keys())
+                .map(|state| {
+                    let before = population_before.get(state).copied().unwrap_or_default();
+                    let after = population_after.get(state).copied().unwrap_or_default();
+                    (*state, before, after)
+                })
+                .find(|(_, before, after)| before != after);
+
+            if let Some((state, before, after)) = violation {
+                result = result.and(Err(ConservationError {
+                    layer: LayerId(layer_idx),
+                    state,
+                    before,
+                    after,
+                }));
+            }
+        }
+
+        result
+    }
+
+    /// For every cell, the destination and index of the first movement it
+    /// attempts, as seen from the current generation
+    fn movement_claims(&self, layer: &Layer) -> Vec<Option<(usize, usize)>> {
+        let grid = &layer.grid;
+
+        (0..grid.n_cells())
+            .into_par_iter()
+            .map_init(
+                || StateMap::with_states(layer.model.n_states()),
+                |state_map, idx| {
+                    let state = grid.cells()[idx];
+                    state_map.count_states(grid.iter_neighbors(idx));
+                    let cell = CellContext::new(idx, state_map, &self.layers);
+
+                    layer
+                        .model
+                        .movements()
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, movement)| movement.is_triggered(state, &cell))
+                        .find_map(|(movement_idx, movement)| {
+                            movement
+                                .offsets()
+                                .iter()
+                                .filter_map(|&(dx, dy)| grid.offset_index(idx, dx, dy))
+                                .find(|&destination| {
+                                    grid.cells()[destination] == *movement.into_node_id()
+                                })
+                                .map(|destination| (destination, movement_idx))
+                        })
+                },
+            )
+            .collect()
+    }
+
+    /// Updates a single cell on every non-block layer, immediately writing
+    /// the result so that later updates can see it
+    fn update_in_place(&mut self, idx: usize) {
+        for layer_idx in 0..self.layers.len() {
+            let layer = &self.layers[layer_idx];
+            if layer.model.is_block_model() {
+                continue;
+            }
+
+            self.scratch.count_states(layer.grid.iter_neighbors(idx));
+
+            let cell = CellContext::new(idx, &self.scratch, &self.layers);
+            let curr_state = layer.grid.cells()[idx];
+            let next_state = match layer.model.fired_edge(curr_state, &cell) {
+                Some(edge_idx) => {
+                    if let Some(collector) = self.statistics.as_mut().filter(|_| layer_idx == 0) {
+                        collector.firings[edge_idx] += 1;
+                    }
+                    *layer.model.all_edges()[edge_idx].to_node_id()
+                }
+                None => curr_state,
+            };
+            self.layers[layer_idx].grid.step_cell_at(idx, next_state);
+        }
+    }
+
+    /// Number of steps taken so far
+    #[inline]
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline]
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// Layers may be edited in any way, so they're all fully evaluated on
+    /// the next step
+    pub fn layers_mut(&mut self) -> impl Iterator<Item = &mut Layer> {
+        self.layers
+            .iter_mut()
+            .for_each(|layer| layer.grid.forget_changes());
+        self.layers.iter_mut()
+    }
+
+    pub fn layer(&self, id: LayerId) -> Option<&Layer> {
+        self.layers.get(id.as_index())
+    }
+
+    /// The neighborhood of the layer's model, if it has one, is applied to
+    /// its grid on the next step
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        let layer = self.layers.get_mut(id.as_index())?;
+        layer.grid.forget_changes();
+
+        Some(layer)
+    }
+
+    pub fn layer_id(&self, name: &str) -> Option<LayerId> {
+        self.layers
+            .iter()
+            .position(|layer| layer.name() == name)
+            .map(LayerId)
+    }
+
+    /// Model of the first layer
+    #[inline]
+    pub fn model(&self) -> &Model {
+        &self.layers[0].model
+    }
+
+    /// The model's neighborhood, if it has one, is applied to the grid on
+    /// the next step
+    #[inline]
+    pub fn model_mut(&mut self) -> &mut Model {
+        // Cells must be evaluated again under the new rules
+        self.layers[0].grid.forget_changes();
+        &mut self.layers[0].model
+    }
+
+    /// Grid of the first layer
+    #[inline]
+    pub fn grid(&self) -> &Grid {
+        &self.layers[0].grid
+    }
+
+    #[inline]
+    pub fn grid_mut(&mut self) -> &mut Grid {
+        &mut self.layers[0].grid
+    }
+}
+
+/// Stepping API shared by every grid backend, so that the same [`Model`]
+/// runs unchanged on any of them
+pub trait Stepper {
+    fn step(&mut self);
+
+    /// Number of steps taken so far
+    fn generation(&self) -> u64;
+
+    fn model(&self) -> &Model;
+
+    /// How many cells are in each state. Unbounded grids leave their
+    /// background state out.
+    fn population(&self) -> BTreeMap<NodeId, usize>;
+
+    fn advance(&mut self, steps: u64) {
+        (0..steps).for_each(|_| self.step());
+    }
+}
+
+impl Stepper for SimulationContext {
+    #[inline]
+    fn step(&mut self) {
+        SimulationContext::step(self);
+    }
+
+    #[inline]
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline]
+    fn model(&self) -> &Model {
+        SimulationContext::model(self)
+    }
+
+    fn population(&self) -> BTreeMap<NodeId, usize> {
+        self.grid().population()
+    }
+}
+
+/// Runs a model on an unbounded [`SparseGrid`], which grows and shrinks with
+/// the pattern.
+///
+/// Only the regular transitions apply, synchronously, so block rules and
+/// movements aren't supported.
+pub struct SparseSimulationContext {
+    model: Model,
+    grid: SparseGrid,
+    generation: u64,
+}
+
+impl SparseSimulationContext {
+    pub fn new(model: Model, mut grid: SparseGrid) -> anyhow::Result<Self> {
+        // Unbounded grids have no other layers to look at
+        ensure!(
+            model.is_outer_totalistic(),
+            "unbounded grids only support transitions counting neighbors on their own layer"
+        );
+
+        if let Some(neighborhood) = model.neighborhood() {
+            grid.set_strategy(neighborhood.clone());
+        }
+
+        // A background cell surrounded by background must stay that way, or
+        // the whole plane would light up
+        let background = grid.background();
+        let mut state_map = StateMap::new();
+        state_map.count_states(grid.strategy().planar_offsets(0, 0).map(|_| background));
+        ensure!(
+            model.next_state(background, &CellContext::new(0, &state_map, &[])) == background,
+            "the background state must be stable on its own"
+        );
+
+        Ok(Self {
+            model,
+            grid,
+            generation: 0,
+        })
+    }
+
+    #[inline]
+    pub fn grid(&self) -> &SparseGrid {
+        &self.grid
+    }
+
+    #[inline]
+    pub fn grid_mut(&mut self) -> &mut SparseGrid {
+        &mut self.grid
+    }
+}
+
+impl Stepper for SparseSimulationContext {
+    fn step(&mut self) {
+        let model = &self.model;
+        // Cells of an unbounded grid have no index, and there are no other
+        // layers to look at
         self.grid
-            .map_cells(&self.state_pool, |curr_state, state_map| {
-                self.model.next_state(curr_state, state_map)
+            .map_cells(model.n_states(), |curr_state, state_map| {
+                model.next_state(curr_state, &CellContext::new(0, state_map, &[]))
             });
+        self.generation += 1;
+    }
+
+    #[inline]
+    fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    #[inline]
+    fn model(&self) -> &Model {
+        &self.model
+    }
+
+    fn population(&self) -> BTreeMap<NodeId, usize> {
+        self.grid.population()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeSet;
+
     use super::*;
-    use crate::grid::test_utils::{game_of_life_grid, to_game_of_life_output};
+    use rstest::rstest;
+
+    use crate::{
+        grid::{
+            neighbor_strategy::{Boundary, NeighboringStrategy},
+            test_utils::{game_of_life_grid, to_game_of_life_output},
+        },
+        model::{Condition, Edge, Movement, MovementKind, Node, NodeId, Operand, Value},
+    };
+
+    /// Two-state model that turns on according to `on_when`, and off otherwise
+    fn follower_model(on_when: Value, operand: Operand, right: Value) -> Model {
+        let off = NodeId(0);
+        let on = NodeId(1);
+
+        let mut model = Model::new();
+        model.add_node(Node::new("Off".to_string()));
+        model.add_node(Node::new("On".to_string()));
+
+        let mut turn_on = Edge::new("Turn on".to_string(), off, on);
+        turn_on.add_condition(Condition {
+            left: on_when,
+            operand,
+            right,
+        });
+        model.add_edge(turn_on);
+
+        let mut turn_off = Edge::new("Turn off".to_string(), on, off);
+        turn_off.add_condition(Condition {
+            left: on_when,
+            operand: operand.negated(),
+            right,
+        });
+        model.add_edge(turn_off);
+
+        model
+    }
 
     #[test]
     fn gol_block_should_remain() {
@@ -43,7 +728,7 @@ mod tests {
         let mut ctx = SimulationContext::new(model, grid);
         ctx.step();
 
-        insta::assert_snapshot!(to_game_of_life_output(&ctx.grid));
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
     }
 
     #[test]
@@ -62,10 +747,10 @@ mod tests {
         let mut ctx = SimulationContext::new(model, grid);
         ctx.step();
 
-        insta::assert_snapshot!("horizontal", to_game_of_life_output(&ctx.grid));
+        insta::assert_snapshot!("horizontal", to_game_of_life_output(ctx.grid()));
         ctx.step();
 
-        insta::assert_snapshot!("vertical", to_game_of_life_output(&ctx.grid));
+        insta::assert_snapshot!("vertical", to_game_of_life_output(ctx.grid()));
     }
 
     #[test]
@@ -79,6 +764,515 @@ mod tests {
             ctx.step();
         }
 
-        insta::assert_snapshot!(to_game_of_life_output(&ctx.grid));
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn layers_should_read_the_previous_generation() {
+        let blinker = "
+            ░░░
+            ░█░
+            ░█░
+            ░█░
+            ░░░
+        ";
+
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(blinker));
+        let follower = follower_model(
+            Value::LayerState(LayerId(0)),
+            Operand::Equal,
+            Value::Absolute(1),
+        );
+        ctx.add_layer("Follower".to_string(), follower, game_of_life_grid(blinker))
+            .unwrap();
+
+        ctx.step();
+
+        let follower = ctx.layer(LayerId(1)).unwrap();
+        assert_eq!(
+            to_game_of_life_output(&follower.grid),
+            to_game_of_life_output(&game_of_life_grid(blinker))
+        );
+    }
+
+    #[test]
+    fn layers_should_count_neighbors_on_other_layers() {
+        let mut ctx = SimulationContext::new(
+            Model::game_of_life(),
+            game_of_life_grid(
+                "
+                ░░░░
+                ░░░░
+                ░░█░
+                ░░░░
+            ",
+            ),
+        );
+        let follower = follower_model(
+            Value::LayerPopulationCount(LayerId(0), NodeId(1)),
+            Operand::GreaterOrEqual,
+            Value::Absolute(1),
+        );
+        let empty = game_of_life_grid(
+            "
+            ░░░░
+            ░░░░
+            ░░░░
+            ░░░░
+        ",
+        );
+        ctx.add_layer("Follower".to_string(), follower, empty)
+            .unwrap();
+
+        ctx.step();
+
+        let follower = ctx.layer(ctx.layer_id("Follower").unwrap()).unwrap();
+        insta::assert_snapshot!(to_game_of_life_output(&follower.grid));
+    }
+
+    #[test]
+    fn layers_should_share_the_lattice() {
+        let mut ctx = SimulationContext::new(
+            Model::game_of_life(),
+            game_of_life_grid(
+                "
+                ░░
+                ░░
+            ",
+            ),
+        );
+
+        let result = ctx.add_layer(
+            "Mismatched".to_string(),
+            Model::game_of_life(),
+            game_of_life_grid("░░░"),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn bbm_ball_should_travel_diagonally() {
+        let grid = game_of_life_grid(
+            "
+            ░░░░░░░
+            ░░░░░░░
+            ░░█░░░░
+            ░░░░░░░
+            ░░░░░░░
+            ░░░░░░░
+            ░░░░░░░
+        ",
+        );
+
+        let mut ctx = SimulationContext::new(Model::billiard_ball_machine(), grid);
+        for _ in 0..3 {
+            ctx.step();
+        }
+
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn bbm_balls_should_bounce_on_collision() {
+        let grid = game_of_life_grid(
+            "
+            ░░░░░░░░░░
+            ░░░░░░░░░░
+            ░░█░░░░░░░
+            ░░░░░░░░░░
+            ░░░░░░░░░░
+            ░░░░░░░░░░
+            ░░░░░░░░░░
+            ░░░░░░░█░░
+            ░░░░░░░░░░
+            ░░░░░░░░░░
+        ",
+        );
+
+        let mut ctx = SimulationContext::new(Model::billiard_ball_machine(), grid);
+        for _ in 0..4 {
+            ctx.step();
+        }
+
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn neighborhoods_set_on_models_should_apply_to_their_grids() {
+        let pattern = "
+            ░░░░░
+            ░██░░
+            ░██░░
+            ░░░░░
+        ";
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(pattern));
+        let layer = ctx
+            .add_layer(
+                "Other".to_string(),
+                Model::game_of_life(),
+                game_of_life_grid(pattern),
+            )
+            .unwrap();
+
+        ctx.model_mut()
+            .set_neighborhood(Some(NeighboringStrategy::Square));
+        ctx.layer_mut(layer)
+            .unwrap()
+            .model
+            .set_neighborhood(Some(NeighboringStrategy::Moore { radius: 2 }));
+        ctx.step();
+
+        assert_eq!(
+            ctx.grid().neighbor_ctx().strategy(),
+            &NeighboringStrategy::Square
+        );
+        assert_eq!(
+            ctx.layer(layer).unwrap().grid.neighbor_ctx().strategy(),
+            &NeighboringStrategy::Moore { radius: 2 }
+        );
+    }
+
+    #[rstest]
+    #[case(5, 5, Boundary::Toroidal)]
+    #[case(7, 6, Boundary::Toroidal)]
+    #[case(5, 5, Boundary::Reflective)]
+    #[case(6, 6, Boundary::Reflective)]
+    #[case(5, 5, Boundary::KleinBottle)]
+    fn bbm_balls_should_be_conserved_across_boundaries(
+        #[case] width: usize,
+        #[case] height: usize,
+        #[case] boundary: Boundary,
+    ) {
+        let mut grid = Grid::empty(width * height, width, NeighboringStrategy::Square);
+        grid.set_boundary(boundary);
+        grid.set_cell_at(0, NodeId(1));
+        grid.set_cell_at(width * height - 1, NodeId(1));
+        grid.set_cell_at(width + 2, NodeId(1));
+
+        let mut ctx = SimulationContext::new(Model::billiard_ball_machine(), grid);
+        for _ in 0..12 {
+            ctx.step();
+            assert_eq!(ctx.grid().population()[&NodeId(1)], 3);
+        }
+    }
+
+    const SOUP: &str = "
+        ░█░░█░░░
+        ██░█░░█░
+        ░░███░░█
+        █░░█░██░
+        ░█░░░█░░
+        ░░██░░░█
+        █░░░█░█░
+        ░██░░░░░
+    ";
+
+    fn run_soup(update_scheme: UpdateScheme, seed: u64, steps: usize) -> String {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(SOUP));
+        ctx.set_update_scheme(update_scheme);
+        ctx.reseed(seed);
+
+        for _ in 0..steps {
+            ctx.step();
+        }
+
+        to_game_of_life_output(ctx.grid())
+    }
+
+    #[rstest]
+    #[case(UpdateScheme::RandomSequential)]
+    #[case(UpdateScheme::LineSweep)]
+    #[case(UpdateScheme::RandomIndependent { probability: 0.5 })]
+    #[case(UpdateScheme::Clocked { min_period: 1, max_period: 4 })]
+    fn update_schemes_should_be_reproducible(#[case] update_scheme: UpdateScheme) {
+        assert_eq!(
+            run_soup(update_scheme, 42, 10),
+            run_soup(update_scheme, 42, 10)
+        );
+    }
+
+    #[test]
+    fn certain_random_independent_updates_should_be_synchronous() {
+        assert_eq!(
+            run_soup(UpdateScheme::RandomIndependent { probability: 1.0 }, 7, 5),
+            run_soup(UpdateScheme::Synchronous, 7, 5)
+        );
+    }
+
+    #[test]
+    fn impossible_random_independent_updates_should_freeze() {
+        assert_eq!(
+            run_soup(UpdateScheme::RandomIndependent { probability: 0.0 }, 7, 5),
+            run_soup(UpdateScheme::Synchronous, 7, 0)
+        );
+        assert_eq!(
+            run_soup(
+                UpdateScheme::RandomIndependent {
+                    probability: f64::NAN
+                },
+                7,
+                5
+            ),
+            run_soup(UpdateScheme::Synchronous, 7, 0)
+        );
+    }
+
+    #[test]
+    fn line_sweep_should_see_earlier_updates() {
+        // The top cell dies first, leaving the middle one with a single
+        // neighbor by the time it is evaluated
+        let mut ctx = SimulationContext::new(
+            Model::game_of_life(),
+            game_of_life_grid(
+                "
+                ░░░
+                ░█░
+                ░█░
+                ░█░
+                ░░░
+            ",
+            ),
+        );
+        ctx.set_update_scheme(UpdateScheme::LineSweep);
+        ctx.step();
+
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn sand_should_fall_and_pile_up() {
+        let grid = game_of_life_grid(
+            "
+            ░░█░░
+            ░░░░░
+            ░░█░░
+            ░░░░░
+            ░░░░░
+        ",
+        );
+
+        let mut ctx = SimulationContext::new(Model::falling_sand(), grid);
+        ctx.set_conservation_check(true);
+        for _ in 0..6 {
+            ctx.try_step().unwrap();
+        }
+
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn first_cell_in_reading_order_should_win_conflicts() {
+        // Both top grains can only slide into the middle cell
+        let grid = game_of_life_grid(
+            "
+            █░█
+            █░█
+            ███
+        ",
+        );
+
+        let mut ctx = SimulationContext::new(Model::falling_sand(), grid);
+        ctx.step();
+
+        insta::assert_snapshot!(to_game_of_life_output(ctx.grid()));
+    }
+
+    #[test]
+    fn non_conservative_movements_should_be_reported() {
+        let mut model = Model::falling_sand();
+        model.add_movement(Movement::new(
+            "Sprout".to_string(),
+            NodeId(1),
+            NodeId(0),
+            vec![(0, -1)],
+            MovementKind::Move { leave: NodeId(1) },
+        ));
+
+        let mut ctx = SimulationContext::new(
+            model,
+            game_of_life_grid(
+                "
+                ░
+                █
+            ",
+            ),
+        );
+        ctx.set_conservation_check(true);
+
+        assert_eq!(
+            ctx.try_step(),
+            Err(ConservationError {
+                layer: LayerId(0),
+                state: NodeId(0),
+                before: 1,
+                after: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn model_neighborhood_should_replace_grid_strategy() {
+        let mut model = Model::game_of_life();
+        model.set_neighborhood(Some(NeighboringStrategy::Moore { radius: 2 }));
+
+        let ctx = SimulationContext::new(model, game_of_life_grid("░░░"));
+
+        assert_eq!(
+            ctx.grid().neighbor_ctx().strategy(),
+            &NeighboringStrategy::Moore { radius: 2 }
+        );
+    }
+
+    #[test]
+    fn sparse_glider_should_travel_past_any_border() {
+        let grid = game_of_life_grid(include_str!("../fixtures/gol/glider.txt"));
+        let sparse = SparseGrid::from_grid(&grid, NodeId(0)).unwrap();
+        let initial_shape = sparse.to_grid().unwrap();
+
+        let mut ctx = SparseSimulationContext::new(Model::game_of_life(), sparse).unwrap();
+        ctx.advance(80);
+
+        assert_eq!(ctx.generation(), 80);
+        assert_eq!(ctx.grid().bounds(), Some(((20, 20), (22, 22))));
+        assert_eq!(ctx.grid().to_grid().unwrap().cells(), initial_shape.cells());
+        // The glider only ever spans a few chunks, which follow it around
+        assert!(ctx.grid().n_chunks() <= 4);
+    }
+
+    /// Cells out of the background state, in the frame of the sparse grid,
+    /// whose origin is the top-left corner of the dense one
+    fn live_cells(dense: &Grid) -> BTreeSet<(isize, isize, NodeId)> {
+        let width = dense.cells_per_row();
+        dense
+            .cells()
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| **cell != NodeId(0))
+            .map(|(idx, cell)| ((idx % width) as isize, (idx / width) as isize, *cell))
+            .collect()
+    }
+
+    #[test]
+    fn sparse_and_dense_grids_should_agree() {
+        // Far enough from the borders that the soup never reaches them
+        let mut padded = String::new();
+        let blank_row = format!("{}\n", "░".repeat(48));
+        padded.push_str(&blank_row.repeat(20));
+        SOUP.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .for_each(|line| padded.push_str(&format!("{0}{line}{0}\n", "░".repeat(20))));
+        padded.push_str(&blank_row.repeat(20));
+
+        let grid = game_of_life_grid(&padded);
+        let sparse = SparseGrid::from_grid(&grid, NodeId(0)).unwrap();
+
+        let mut dense = SimulationContext::new(Model::game_of_life(), grid);
+        let mut sparse = SparseSimulationContext::new(Model::game_of_life(), sparse).unwrap();
+
+        for _ in 0..12 {
+            Stepper::step(&mut dense);
+            Stepper::step(&mut sparse);
+
+            let sparse_cells: BTreeSet<_> = sparse.grid().cells().collect();
+            assert_eq!(live_cells(dense.grid()), sparse_cells);
+        }
+        assert_eq!(dense.generation(), Stepper::generation(&sparse));
+    }
+
+    #[test]
+    fn sparse_grids_should_reject_unsupported_models() {
+        let grid = SparseGrid::new(NeighboringStrategy::SquareAndCorners, NodeId(0));
+        assert!(SparseSimulationContext::new(Model::billiard_ball_machine(), grid).is_err());
+
+        for on_when in [
+            Value::LayerState(LayerId(0)),
+            Value::LayerPopulationCount(LayerId(0), NodeId(1)),
+        ] {
+            let grid = SparseGrid::new(NeighboringStrategy::SquareAndCorners, NodeId(0));
+            let follower = follower_model(on_when, Operand::Equal, Value::Absolute(1));
+            assert!(SparseSimulationContext::new(follower, grid).is_err());
+        }
+
+        // Turns every cell on, background included
+        let grid = SparseGrid::new(NeighboringStrategy::SquareAndCorners, NodeId(0));
+        let always_on = follower_model(
+            Value::PopulationCount(NodeId(1)),
+            Operand::GreaterOrEqual,
+            Value::Absolute(0),
+        );
+        assert!(SparseSimulationContext::new(always_on, grid).is_err());
+    }
+
+    /// Brian's Brain: firing cells always start dying, and dying ones always
+    /// turn off, whatever their neighbors
+    fn brians_brain() -> Model {
+        let (off, firing, dying) = (NodeId(0), NodeId(1), NodeId(2));
+
+        let mut model = Model::new();
+        ["Off", "Firing", "Dying"]
+            .into_iter()
+            .for_each(|name| model.add_node(Node::new(name.to_string())));
+
+        let mut ignite = Edge::new("Ignite".to_string(), off, firing);
+        ignite.add_condition(Condition {
+            left: Value::PopulationCount(firing),
+            operand: Operand::Equal,
+            right: Value::Absolute(2),
+        });
+        model.add_edge(ignite);
+        model.add_edge(Edge::new("Fade".to_string(), firing, dying));
+        model.add_edge(Edge::new("Rest".to_string(), dying, off));
+
+        model
+    }
+
+    #[test]
+    fn change_tracking_should_match_full_updates() {
+        let mut grid = Grid::empty(40 * 30, 40, NeighboringStrategy::SquareAndCorners);
+        // Two spaceships flying in opposite directions
+        [(10, 20, 1), (11, 20, 1), (10, 21, 2), (11, 21, 2)]
+            .into_iter()
+            .chain([(30, 8, 1), (31, 8, 1), (30, 7, 2), (31, 7, 2)])
+            .for_each(|(x, y, state)| grid.set_cell_at(y * 40 + x, NodeId(state)));
+        let mut reference = Grid::empty(40 * 30, 40, NeighboringStrategy::SquareAndCorners);
+        (0..grid.n_cells()).for_each(|idx| reference.set_cell_at(idx, grid.cells()[idx]));
+
+        let model = brians_brain();
+        let mut ctx = SimulationContext::new(brians_brain(), grid);
+
+        for generation in 0..12 {
+            reference.map_cells(|cell, state_map| {
+                model.next_state(cell, &CellContext::new(0, state_map, &[]))
+            });
+            ctx.step();
+
+            assert_eq!(
+                ctx.grid().cells(),
+                reference.cells(),
+                "generation {generation}"
+            );
+        }
+
+        // Only the spaceships and their surroundings are left to evaluate
+        let n_active_cells = ctx.grid().n_active_cells().unwrap();
+        assert!(n_active_cells < ctx.grid().n_cells() / 10);
+    }
+
+    #[test]
+    fn edits_should_trigger_a_full_evaluation() {
+        let mut ctx = SimulationContext::new(brians_brain(), game_of_life_grid(SOUP));
+        assert_eq!(ctx.grid().n_active_cells(), None);
+
+        ctx.step();
+        assert!(ctx.grid().n_active_cells().is_some());
+
+        ctx.model_mut();
+        assert_eq!(ctx.grid().n_active_cells(), None);
+
+        ctx.step();
+        ctx.grid_mut().set_boundary(Boundary::Toroidal);
+        assert_eq!(ctx.grid().n_active_cells(), None);
     }
 }
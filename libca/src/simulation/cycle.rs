@@ -0,0 +1,320 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use crate::{
+    grid::{neighbor_strategy::Boundary, Grid},
+    model::NodeId,
+};
+
+use super::{snapshot::RunLengths, SimulationContext};
+
+/// Translations are only looked for among this many alignments along each
+/// axis, which only grids whose rows or columns are alike run out of
+const MAX_ALIGNMENTS: usize = 16;
+
+/// Configuration that came back, as found by [`CycleDetector`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cycle {
+    /// Generation the configuration first appeared at
+    pub start: u64,
+    /// Steps from the first observed generation to `start`
+    pub transient: u64,
+    pub period: u64,
+    /// How far the configuration moved along `x` and `y` over a period, by
+    /// the shortest way around toroidal grids
+    pub displacement: (isize, isize),
+}
+
+impl Cycle {
+    #[inline]
+    pub fn is_fixed_point(&self) -> bool {
+        self.period == 1 && self.displacement == (0, 0)
+    }
+
+    /// Whether the configuration travels, as spaceships do
+    #[inline]
+    pub fn is_translation(&self) -> bool {
+        self.displacement != (0, 0)
+    }
+}
+
+/// Remembers every configuration of a run to tell when one repeats. They're
+/// kept run-length encoded and looked up by hash, then compared cell by cell
+/// so that collisions can't pass for cycles.
+///
+/// Configurations span every layer. On planar toroidal grids they also match
+/// translated copies of earlier ones. Runs with a random update scheme may
+/// go on differently after a configuration repeats, so their cycles are only
+/// cycles of configurations.
+#[derive(Default)]
+pub struct CycleDetector {
+    first_generation: Option<u64>,
+    /// Generation and cells of every layer of each configuration observed
+    configurations: Vec<(u64, Vec<RunLengths>)>,
+    /// Indices in `configurations` by hash
+    exact: HashMap<u64, Vec<usize>>,
+    /// Indices in `configurations` by hash once moved to a canonical
+    /// alignment, with the `(x, y)` alignment
+    translated: HashMap<u64, Vec<(usize, usize, usize)>>,
+}
+
+impl CycleDetector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the current configuration of `ctx`, returning the cycle it
+    /// closes if it was already seen
+    pub fn observe(&mut self, ctx: &SimulationContext) -> Option<Cycle> {
+        let generation = ctx.generation();
+        let first_generation = *self.first_generation.get_or_insert(generation);
+        let cycle = |start: u64, displacement| Cycle {
+            start,
+            transient: start - first_generation,
+            period: generation - start,
+            displacement,
+        };
+
+        // Equal cells give equal runs, so they're compared without expanding
+        let configuration: Vec<_> = ctx
+            .layers()
+            .iter()
+            .map(|layer| RunLengths::encode(layer.grid.cells()))
+            .collect();
+        let hash = ctx.state_hash();
+        let exact = self.exact.entry(hash).or_default();
+        let earlier = exact
+            .iter()
+            .map(|&idx| &self.configurations[idx])
+            .find(|(_, cells)| *cells == configuration);
+        if let Some((start, _)) = earlier {
+            return Some(cycle(*start, (0, 0)));
+        }
+
+        let idx = self.configurations.len();
+        exact.push(idx);
+        self.configurations.push((generation, configuration));
+
+        let grid = ctx.grid();
+        let [width, height, depth] = grid.slice_extents();
+        if grid.boundary() != Boundary::Toroidal || depth > 1 || width * height != grid.n_cells() {
+            return None;
+        }
+
+        let (hash, x, y) = canonical_alignment(ctx)?;
+        let translated = self.translated.entry(hash).or_default();
+        let earlier = translated.iter().find(|(start_idx, start_x, start_y)| {
+            let (_, configuration) = &self.configurations[*start_idx];
+            configuration.iter().zip(ctx.layers()).all(|(runs, layer)| {
+                // Runs were encoded from a grid of this very lattice
+                let Ok(cells) = runs.decode(grid.n_cells()) else {
+                    return false;
+                };
+                aligned(&cells, width, height, *start_x, *start_y).eq(aligned(
+                    layer.grid.cells(),
+                    width,
+                    height,
+                    x,
+                    y,
+                ))
+            })
+        });
+        if let Some(&(start_idx, start_x, start_y)) = earlier {
+            let start = self.configurations[start_idx].0;
+            let wrap = |offset: usize, start: usize, len: usize| {
+                let delta = (offset + len - start) % len;
+                if delta > len / 2 {
+                    delta as isize - len as isize
+                } else {
+                    delta as isize
+                }
+            };
+            return Some(cycle(
+                start,
+                (wrap(x, start_x, width), wrap(y, start_y, height)),
+            ));
+        }
+        translated.push((idx, x, y));
+
+        None
+    }
+}
+
+/// Picks the alignment of the configuration that doesn't depend on where it
+/// lies on the torus, as its hash and the `(x, y)` cell moved to the origin.
+/// Candidates are the rotations giving the smallest column and row profiles,
+/// and the one with the smallest hash wins.
+fn canonical_alignment(ctx: &SimulationContext) -> Option<(u64, usize, usize)> {
+    let [width, height, _] = ctx.grid().slice_extents();
+    let mut columns = vec![0u64; width];
+    let mut rows = vec![0u64; height];
+    for layer in ctx.layers() {
+        for (idx, state) in layer.grid.cells().iter().enumerate() {
+            let weight = mix(state.as_index() as u64 + 1);
+            columns[idx % width] = columns[idx % width].wrapping_add(weight);
+            rows[idx / width] = rows[idx / width].wrapping_add(weight);
+        }
+    }
+
+    let xs = smallest_rotations(&columns);
+    let ys = smallest_rotations(&rows);
+    if xs.len() > MAX_ALIGNMENTS || ys.len() > MAX_ALIGNMENTS {
+        return None;
+    }
+
+    xs.iter()
+        .flat_map(|&x| ys.iter().map(move |&y| (x, y)))
+        .map(|(x, y)| {
+            let grids = ctx.layers().iter().map(|layer| &layer.grid);
+            (aligned_hash(grids, x, y), x, y)
+        })
+        .min()
+}
+
+/// Offsets at which rotating `profile` gives its lexicographically smallest
+/// rotation
+fn smallest_rotations(profile: &[u64]) -> Vec<usize> {
+    let rotated = |offset: usize| profile[offset..].iter().chain(&profile[..offset]);
+    let smallest = (0..profile.len())
+        .min_by(|&a, &b| rotated(a).cmp(rotated(b)))
+        .unwrap_or_default();
+
+    (0..profile.len())
+        .filter(|&offset| rotated(offset).eq(rotated(smallest)))
+        .collect()
+}
+
+fn aligned_hash<'g>(grids: impl Iterator<Item = &'g Grid>, x: usize, y: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for grid in grids {
+        let [width, height, _] = grid.slice_extents();
+        aligned(grid.cells(), width, height, x, y).for_each(|state| state.hash(&mut hasher));
+    }
+
+    hasher.finish()
+}
+
+/// Cells of a `width` by `height` torus in reading order, starting from the
+/// one at `(x, y)`
+fn aligned(
+    cells: &[NodeId],
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+) -> impl Iterator<Item = NodeId> + '_ {
+    (0..height).flat_map(move |row| {
+        (0..width).map(move |column| cells[(row + y) % height * width + (column + x) % width])
+    })
+}
+
+/// Spreads the bits of small values, so that sums of them rarely collide
+fn mix(value: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl SimulationContext {
+    /// Hash of the cells of every layer, equal for equal configurations
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for layer in &self.layers {
+            layer.grid.cells().hash(&mut hasher);
+        }
+
+        hasher.finish()
+    }
+
+    /// Steps until a configuration repeats, for at most `max_steps` steps.
+    /// A run that's already stable takes one step to be reported.
+    pub fn run_until_stable(&mut self, max_steps: u64) -> Option<Cycle> {
+        let mut detector = CycleDetector::new();
+        detector.observe(self);
+
+        for _ in 0..max_steps {
+            self.step();
+            if let Some(cycle) = detector.observe(self) {
+                return Some(cycle);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::test_utils::game_of_life_grid, model::Model};
+    use rstest::rstest;
+
+    fn run(pattern: &str, boundary: Boundary, max_steps: u64) -> Option<Cycle> {
+        let mut grid = game_of_life_grid(pattern);
+        grid.set_boundary(boundary);
+        SimulationContext::new(Model::game_of_life(), grid).run_until_stable(max_steps)
+    }
+
+    const BLOCK: &str = include_str!("../../fixtures/gol/block.txt");
+    const BLINKER: &str = include_str!("../../fixtures/gol/blinker.txt");
+    const GLIDER: &str = include_str!("../../fixtures/gol/glider.txt");
+
+    /// Dies out after a single step
+    const DOMINO: &str = "
+        ░░░░
+        ░██░
+        ░░░░
+    ";
+
+    #[rstest]
+    #[case(BLOCK, 0, 1)]
+    #[case(BLINKER, 0, 2)]
+    #[case(DOMINO, 1, 1)]
+    fn oscillators_should_report_their_period(
+        #[case] pattern: &str,
+        #[case] transient: u64,
+        #[case] period: u64,
+    ) {
+        let cycle = run(pattern, Boundary::Open, 10).unwrap();
+
+        assert_eq!((cycle.transient, cycle.period), (transient, period));
+        assert_eq!(cycle.is_fixed_point(), period == 1);
+        assert!(!cycle.is_translation());
+    }
+
+    #[test]
+    fn gliders_should_be_found_translated_on_tori() {
+        let cycle = run(GLIDER, Boundary::Toroidal, 50).unwrap();
+
+        assert_eq!(cycle.transient, 0);
+        assert_eq!(cycle.period, 4);
+        assert_eq!(cycle.displacement, (1, 1));
+        assert!(cycle.is_translation());
+    }
+
+    #[test]
+    fn hash_collisions_should_not_pass_for_cycles() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(BLINKER));
+        let mut detector = CycleDetector::new();
+        detector.observe(&ctx);
+
+        ctx.step();
+        // Files the first configuration under the hash of the second one
+        detector.exact.insert(ctx.state_hash(), vec![0]);
+
+        assert_eq!(detector.observe(&ctx), None);
+        ctx.step();
+        assert_eq!(detector.observe(&ctx).unwrap().period, 2);
+    }
+
+    #[test]
+    fn runs_should_give_up_after_max_steps() {
+        assert_eq!(run(GLIDER, Boundary::Toroidal, 3), None);
+        assert_eq!(run(BLOCK, Boundary::Open, 0), None);
+
+        let blinker = run(BLINKER, Boundary::Toroidal, 3).unwrap();
+        assert_eq!((blinker.period, blinker.displacement), (2, (0, 0)));
+    }
+}
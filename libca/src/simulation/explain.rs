@@ -0,0 +1,204 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use crate::{
+    grid::GridError,
+    layer::CellContext,
+    model::{Condition, NodeId},
+    state_map::StateMap,
+};
+
+use super::SimulationContext;
+
+/// Why a cell of the main layer is about to take its next state, as found by
+/// [`SimulationContext::explain`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub x: usize,
+    pub y: usize,
+    pub state: NodeId,
+    /// State of each neighbor, in the order of the neighboring strategy
+    pub neighbors: Vec<NodeId>,
+    /// How many neighbors are in each state, leaving out those with none
+    pub counts: BTreeMap<NodeId, u32>,
+    /// Edges leaving the current state, in the order they're evaluated.
+    /// Those after the one that fired are never evaluated, they're traced
+    /// all the same.
+    pub candidates: Vec<EdgeTrace>,
+    /// Index in `candidates` of the edge that fired
+    pub fired: Option<usize>,
+    pub next_state: NodeId,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EdgeTrace {
+    pub name: String,
+    pub to_node: NodeId,
+    pub conditions: Vec<ConditionTrace>,
+    /// Whether every condition holds
+    pub satisfied: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConditionTrace {
+    pub condition: Condition,
+    /// Values both sides of the condition resolved to
+    pub left: u32,
+    pub right: u32,
+    pub satisfied: bool,
+}
+
+impl SimulationContext {
+    /// Traces how the model picks the next state of the cell at `(x, y)` on
+    /// the main layer, as if it were stepped synchronously now.
+    ///
+    /// Block rules and movements aren't traced, and block models don't look
+    /// at their edges at all.
+    pub fn explain(&self, x: usize, y: usize) -> Result<Explanation, GridError> {
+        let layer = &self.layers[0];
+        let idx = layer.grid.index(x, y)?;
+        let state = layer.grid.cells()[idx];
+
+        let neighbors: Vec<_> = layer.grid.iter_neighbors(idx).collect();
+        let mut state_map = StateMap::with_states(layer.model.n_states());
+        state_map.count_states(neighbors.iter().copied());
+        let counts = (0..state_map.n_states())
+            .map(NodeId::from_index)
+            .map(|state| (state, state_map.get_count(state)))
+            .filter(|(_, count)| *count > 0)
+            .collect();
+
+        let cell = CellContext::new(idx, &state_map, &self.layers);
+        let model = &layer.model;
+        // Indices in the model of the edges leaving the current state
+        let edge_indices: Vec<_> = (0..model.all_edges().len())
+            .filter(|&edge_idx| *model.all_edges()[edge_idx].from_node_id() == state)
+            .filter(|_| !model.is_block_model())
+            .collect();
+        let candidates: Vec<_> = edge_indices
+            .iter()
+            .map(|&edge_idx| &model.all_edges()[edge_idx])
+            .map(|edge| {
+                let conditions: Vec<_> = edge
+                    .conditions()
+                    .iter()
+                    .map(|condition| {
+                        let left = condition.left().to_absolute(&cell);
+                        let right = condition.right().to_absolute(&cell);
+                        ConditionTrace {
+                            condition: condition.clone(),
+                            left,
+                            right,
+                            satisfied: condition.is_satisfied(&cell),
+                        }
+                    })
+                    .collect();
+
+                EdgeTrace {
+                    name: edge.name().to_string(),
+                    to_node: *edge.to_node_id(),
+                    satisfied: edge.transition(state, &cell).is_some(),
+                    conditions,
+                }
+            })
+            .collect();
+
+        // The edge the model itself picks, so that traces can't disagree with
+        // stepping
+        let fired = model
+            .fired_edge(state, &cell)
+            .and_then(|fired| edge_indices.iter().position(|&edge_idx| edge_idx == fired));
+        Ok(Explanation {
+            x,
+            y,
+            state,
+            neighbors,
+            counts,
+            next_state: fired.map_or(state, |idx| candidates[idx].to_node),
+            candidates,
+            fired,
+        })
+    }
+}
+
+impl Display for Explanation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Cell ({}, {}) in state #{}",
+            self.x,
+            self.y,
+            self.state.as_index()
+        )?;
+        let counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(state, count)| format!("#{}: {count}", state.as_index()))
+            .collect();
+        writeln!(f, "Neighbors: {}", counts.join(", "))?;
+
+        for (idx, edge) in self.candidates.iter().enumerate() {
+            let status = match self.fired {
+                Some(fired) if fired == idx => "fired",
+                Some(fired) if fired < idx => "not evaluated",
+                _ if edge.satisfied => "satisfied",
+                _ => "failed",
+            };
+            writeln!(
+                f,
+                "  {} -> #{} ({status})",
+                edge.name,
+                edge.to_node.as_index()
+            )?;
+            for condition in &edge.conditions {
+                let operand: &'static str = condition.condition.operand.into();
+                writeln!(
+                    f,
+                    "    {:?} {operand} {:?}: {} {operand} {} is {}",
+                    condition.condition.left(),
+                    condition.condition.right(),
+                    condition.left,
+                    condition.right,
+                    condition.satisfied
+                )?;
+            }
+        }
+
+        write!(f, "Next state: #{}", self.next_state.as_index())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::test_utils::game_of_life_grid, model::Model};
+
+    const BLINKER: &str = include_str!("../../fixtures/gol/blinker.txt");
+
+    #[test]
+    fn explanations_should_trace_the_edge_that_fired() {
+        let ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(BLINKER));
+
+        let explanation = ctx.explain(1, 2).unwrap();
+
+        assert_eq!(explanation.state, NodeId(0));
+        assert_eq!(explanation.neighbors.len(), 8);
+        assert_eq!(explanation.counts, [(NodeId(0), 5), (NodeId(1), 3)].into());
+        assert_eq!(explanation.candidates.len(), 1);
+        assert_eq!(explanation.candidates[0].name, "Reproduction");
+        assert_eq!(explanation.candidates[0].conditions[0].left, 3);
+        assert_eq!(explanation.fired, Some(0));
+        assert_eq!(explanation.next_state, NodeId(1));
+    }
+
+    #[test]
+    fn explanations_should_show_failed_conditions() {
+        let ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(BLINKER));
+
+        let explanation = ctx.explain(2, 1).unwrap();
+
+        assert_eq!(explanation.fired, Some(0));
+        assert_eq!(explanation.next_state, NodeId(0));
+        insta::assert_snapshot!(explanation.to_string());
+        assert!(ctx.explain(5, 0).is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use std::collections::VecDeque;
+
+use anyhow::ensure;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{model::NodeId, update_scheme::CellClock};
+
+use super::SimulationContext;
+
+/// Last generations of a run, kept as the cells each step changed so that
+/// they can be undone one at a time
+#[derive(Clone)]
+pub(super) struct History {
+    capacity: usize,
+    /// Oldest step first
+    frames: VecDeque<Frame>,
+    /// Cells of every layer as the last step left them
+    tip: Vec<Vec<NodeId>>,
+}
+
+/// Undoes a single step, along with the edits made right before it
+#[derive(Clone)]
+struct Frame {
+    generation: u64,
+    rng: ChaCha8Rng,
+    /// Only kept when the step drew new clocks
+    clocks: Option<Vec<CellClock>>,
+    /// For each layer, the index and former state of the cells edited since
+    /// the previous step
+    edits: Vec<Changes>,
+    /// For each layer, the index and former state of the cells that changed
+    changes: Vec<Changes>,
+}
+
+type Changes = Vec<(usize, NodeId)>;
+
+/// State of the run right before a step, taken by [`History::before_step`]
+pub(super) struct PendingFrame {
+    generation: u64,
+    rng: ChaCha8Rng,
+    /// Only kept when the step may draw new clocks
+    clocks: Option<Vec<CellClock>>,
+    edits: Vec<Changes>,
+}
+
+impl History {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            tip: Vec::new(),
+        }
+    }
+
+    /// Whether `tip` still has the shape of the layers of `ctx`
+    fn matches(&self, ctx: &SimulationContext) -> bool {
+        self.tip.len() == ctx.layers.len()
+            && self
+                .tip
+                .iter()
+                .zip(&ctx.layers)
+                .all(|(tip, layer)| tip.len() == layer.grid.n_cells())
+    }
+
+    /// Brings `tip` up to date with the cells of `ctx`, returning the index
+    /// and former state of those that differed
+    fn catch_up(&mut self, ctx: &SimulationContext) -> Vec<Changes> {
+        self.tip
+            .iter_mut()
+            .zip(&ctx.layers)
+            .map(|(tip, layer)| {
+                tip.iter_mut()
+                    .zip(layer.grid.cells())
+                    .enumerate()
+                    .filter(|(_, (before, after))| before != after)
+                    .map(|(idx, (before, after))| (idx, std::mem::replace(before, *after)))
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub(super) fn before_step(&mut self, ctx: &SimulationContext) -> PendingFrame {
+        // Layers or lattices changed, which earlier frames can't undo
+        if !self.matches(ctx) {
+            self.frames.clear();
+            self.tip = ctx
+                .layers
+                .iter()
+                .map(|layer| layer.grid.cells().to_vec())
+                .collect();
+        }
+
+        // Clocks are only drawn when there aren't any for every cell
+        let may_draw_clocks = ctx.clocks.len() != ctx.grid().n_cells();
+        PendingFrame {
+            generation: ctx.generation,
+            rng: ctx.rng.clone(),
+            clocks: may_draw_clocks.then(|| ctx.clocks.clone()),
+            edits: self.catch_up(ctx),
+        }
+    }
+
+    pub(super) fn after_step(&mut self, pending: PendingFrame, ctx: &SimulationContext) {
+        if !self.matches(ctx) {
+            self.frames.clear();
+            self.tip.clear();
+            return;
+        }
+
+        let changes = self.catch_up(ctx);
+
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        if self.capacity > 0 {
+            self.frames.push_back(Frame {
+                generation: pending.generation,
+                rng: pending.rng,
+                clocks: pending.clocks.filter(|clocks| *clocks != ctx.clocks),
+                edits: pending.edits,
+                changes,
+            });
+        }
+    }
+}
+
+impl SimulationContext {
+    /// Starts keeping the last `capacity` generations, so that the run can
+    /// be stepped back. Each one only holds the cells its step changed, and
+    /// those edited right before it.
+    ///
+    /// Any history kept so far is dropped.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History::new(capacity));
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Earliest generation the run can be stepped back to, if history is
+    /// enabled
+    pub fn oldest_generation(&self) -> Option<u64> {
+        let history = self.history.as_ref()?;
+        Some(
+            history
+                .frames
+                .front()
+                .map_or(self.generation, |frame| frame.generation),
+        )
+    }
+
+    /// Undoes the last step, returning whether there was one to undo.
+    ///
+    /// Cells edited since that step are put back as the step left them, while
+    /// edits made before it are kept until the run is stepped back once more.
+    /// Changes to models, layers or the lattice aren't recorded.
+    pub fn step_back(&mut self) -> bool {
+        let Some(mut history) = self.history.take() else {
+            return false;
+        };
+        let frame = match history.matches(self) {
+            true => history.frames.pop_back(),
+            false => {
+                history.frames.clear();
+                None
+            }
+        };
+        let Some(frame) = frame else {
+            self.history = Some(history);
+            return false;
+        };
+
+        for (((tip, changes), edits), layer) in history
+            .tip
+            .iter_mut()
+            .zip(&frame.changes)
+            .zip(&frame.edits)
+            .zip(&mut self.layers)
+        {
+            for &(idx, state) in changes {
+                tip[idx] = state;
+            }
+            for (idx, state) in tip.iter().enumerate() {
+                if layer.grid.cells()[idx] != *state {
+                    layer.grid.set_cell_at(idx, *state);
+                }
+            }
+            layer.grid.forget_changes();
+
+            // Stepping back again also undoes the edits
+            for &(idx, state) in edits {
+                tip[idx] = state;
+            }
+        }
+
+        self.history = Some(history);
+        self.generation = frame.generation;
+        self.rng = frame.rng;
+        if let Some(clocks) = frame.clocks {
+            self.clocks = clocks;
+        }
+
+        true
+    }
+
+    /// Steps the run back until it's at `generation`, which must still be in
+    /// the history
+    pub fn jump_to(&mut self, generation: u64) -> anyhow::Result<()> {
+        let oldest = self
+            .oldest_generation()
+            .ok_or_else(|| anyhow::anyhow!("history is disabled"))?;
+        ensure!(
+            (oldest..=self.generation).contains(&generation),
+            "generation {generation} isn't in the history, which goes from {oldest} to {}",
+            self.generation
+        );
+
+        while self.generation > generation {
+            ensure!(self.step_back(), "the history no longer matches the layers");
+        }
+
+        Ok(())
+    }
+
+    /// Starts a separate run from a past generation, leaving this one as is.
+    /// The new run keeps the history up to that generation.
+    pub fn branch(&self, generation: u64) -> anyhow::Result<SimulationContext> {
+        let mut saved = Vec::new();
+        self.save_snapshot(&mut saved)?;
+
+        let mut branch = SimulationContext::load_snapshot(saved.as_slice())?;
+        branch.history = self.history.clone();
+        branch.jump_to(generation)?;
+
+        Ok(branch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::test_utils::{game_of_life_grid, game_of_life_soup},
+        model::Model,
+        update_scheme::UpdateScheme,
+    };
+    use rstest::rstest;
+
+    const GLIDER: &str = include_str!("../../fixtures/gol/glider.txt");
+    const BLINKER: &str = include_str!("../../fixtures/gol/blinker.txt");
+
+    fn soup(update_scheme: UpdateScheme) -> SimulationContext {
+        let grid = game_of_life_soup(16, 16, 0.4, 3);
+
+        let mut ctx = SimulationContext::new(Model::game_of_life(), grid);
+        ctx.set_update_scheme(update_scheme);
+        ctx.reseed(8);
+        ctx
+    }
+
+    #[rstest]
+    #[case(UpdateScheme::Synchronous)]
+    #[case(UpdateScheme::RandomSequential)]
+    #[case(UpdateScheme::Clocked { min_period: 1, max_period: 3 })]
+    fn stepping_back_should_replay_identically(#[case] update_scheme: UpdateScheme) {
+        let mut ctx = soup(update_scheme);
+        ctx.enable_history(10);
+
+        let runs: Vec<_> = (0..6)
+            .map(|_| {
+                ctx.step();
+                ctx.grid().cells().to_vec()
+            })
+            .collect();
+        assert!(ctx.grid().initialization().is_some());
+
+        assert!(ctx.step_back());
+        assert!(ctx.step_back());
+        assert_eq!(ctx.generation(), 4);
+        // Rewound cells no longer follow from the initializer alone
+        assert_eq!(ctx.grid().initialization(), None);
+        assert_eq!(ctx.grid().cells(), runs[3]);
+
+        ctx.step();
+        ctx.step();
+        assert_eq!(ctx.grid().cells(), runs[5]);
+    }
+
+    #[test]
+    fn history_should_be_bounded() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(GLIDER));
+        assert!(!ctx.step_back());
+
+        ctx.enable_history(3);
+        (0..5).for_each(|_| ctx.step());
+
+        assert_eq!(ctx.oldest_generation(), Some(2));
+        assert!(ctx.jump_to(1).is_err());
+        assert!(ctx.jump_to(6).is_err());
+        (0..3).for_each(|_| assert!(ctx.step_back()));
+        assert!(!ctx.step_back());
+        assert_eq!(ctx.generation(), 2);
+    }
+
+    #[test]
+    fn edits_after_the_last_step_should_be_undone() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(GLIDER));
+        ctx.enable_history(4);
+        ctx.grid_mut().set(5, 4, NodeId(1)).unwrap();
+        let edited = ctx.grid().cells().to_vec();
+
+        ctx.step();
+        ctx.grid_mut().set(0, 4, NodeId(1)).unwrap();
+        ctx.step_back();
+
+        assert_eq!(ctx.grid().cells(), edited);
+    }
+
+    #[test]
+    fn edits_between_steps_should_be_undone_further_back() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(BLINKER));
+        ctx.enable_history(4);
+        let start = ctx.grid().cells().to_vec();
+
+        ctx.step();
+        let block = game_of_life_grid("██\n██");
+        ctx.grid_mut().paste(&block, 3, 3).unwrap();
+        let edited = ctx.grid().cells().to_vec();
+        ctx.step();
+        ctx.step();
+
+        ctx.jump_to(1).unwrap();
+        assert_eq!(ctx.grid().cells(), edited);
+
+        ctx.jump_to(0).unwrap();
+        assert_eq!(ctx.grid().cells(), start);
+        assert_eq!(ctx.grid().population()[&NodeId(1)], 3);
+
+        (0..3).for_each(|_| ctx.step());
+        let mut branch = ctx.branch(0).unwrap();
+        assert_eq!(branch.grid().cells(), start);
+        branch.step();
+        assert!(branch.step_back());
+        assert_eq!(branch.grid().cells(), start);
+    }
+
+    #[test]
+    fn branches_should_leave_the_original_run_untouched() {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(GLIDER));
+        ctx.enable_history(8);
+        let start = ctx.grid().cells().to_vec();
+        (0..4).for_each(|_| ctx.step());
+        let current = ctx.grid().cells().to_vec();
+
+        let mut branch = ctx.branch(0).unwrap();
+
+        assert_eq!(branch.generation(), 0);
+        assert_eq!(branch.grid().cells(), start);
+        assert_eq!(ctx.generation(), 4);
+        assert_eq!(ctx.grid().cells(), current);
+
+        (0..4).for_each(|_| branch.step());
+        assert_eq!(branch.grid().cells(), current);
+        assert!(ctx.branch(5).is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::io::{Read, Write};
+
+use anyhow::{ensure, Context};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    grid::{neighbor_strategy::NeighboringContext, Grid, Initialization},
+    layer::Layer,
+    model::{Model, NodeId},
+    state_map::StateMap,
+    update_scheme::{CellClock, UpdateScheme},
+};
+
+use super::SimulationContext;
+
+/// Version written into new snapshots. Snapshots of any other version are
+/// rejected, rather than resumed into a different run.
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything needed to resume a run, generic over how models are held so
+/// that the same layout is written from borrowed models and read back into
+/// owned ones
+#[derive(Serialize, Deserialize)]
+struct Snapshot<M> {
+    version: u32,
+    generation: u64,
+    update_scheme: UpdateScheme,
+    seed: u64,
+    rng: ChaCha8Rng,
+    clocks: Vec<CellClock>,
+    conservation_check: bool,
+    layers: Vec<LayerSnapshot<M>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct LayerSnapshot<M> {
+    name: String,
+    model: M,
+    neighbor_ctx: NeighboringContext,
+    cells: RunLengths,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    initialization: Option<Initialization>,
+}
+
+/// Cells in reading order, as runs of `(state, length)`. Equal cells always
+/// give equal runs.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub(super) struct RunLengths(Vec<(usize, usize)>);
+
+impl RunLengths {
+    pub(super) fn encode(cells: &[NodeId]) -> Self {
+        let mut runs: Vec<(usize, usize)> = Vec::new();
+        for cell in cells.iter().map(|cell| cell.as_index()) {
+            match runs.last_mut() {
+                Some((state, len)) if *state == cell => *len += 1,
+                _ => runs.push((cell, 1)),
+            }
+        }
+
+        Self(runs)
+    }
+
+    /// Expands the runs back into `n_cells` cells. Runs are checked to add
+    /// up first, so that a corrupt length can't claim unbounded memory.
+    pub(super) fn decode(&self, n_cells: usize) -> anyhow::Result<Vec<NodeId>> {
+        let total = self
+            .0
+            .iter()
+            .try_fold(0usize, |total, (_, len)| total.checked_add(*len));
+        ensure!(
+            total == Some(n_cells),
+            "runs don't add up to the {n_cells} cells of the grid"
+        );
+
+        Ok(self
+            .0
+            .iter()
+            .flat_map(|&(state, len)| std::iter::repeat_n(NodeId::from_index(state), len))
+            .collect())
+    }
+}
+
+impl SimulationContext {
+    /// Writes the whole run to `writer` as RON: layers with their models,
+    /// lattice and run-length encoded cells, along with the generation,
+    /// update scheme and random number generator.
+    pub fn save_snapshot(&self, writer: impl Write) -> anyhow::Result<()> {
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            generation: self.generation,
+            update_scheme: self.update_scheme,
+            seed: self.seed,
+            rng: self.rng.clone(),
+            clocks: self.clocks.clone(),
+            conservation_check: self.conservation_check,
+            layers: self
+                .layers
+                .iter()
+                .map(|layer| LayerSnapshot {
+                    name: layer.name.clone(),
+                    model: &layer.model,
+                    neighbor_ctx: layer.grid.neighbor_ctx().clone(),
+                    cells: RunLengths::encode(layer.grid.cells()),
+                    initialization: layer.grid.initialization().cloned(),
+                })
+                .collect(),
+        };
+
+        ron::ser::to_writer(writer, &snapshot)?;
+        Ok(())
+    }
+
+    /// Reads back a run written by [`SimulationContext::save_snapshot`], which
+    /// then steps exactly as the original would have
+    pub fn load_snapshot(reader: impl Read) -> anyhow::Result<Self> {
+        let snapshot: Snapshot<Model> = ron::de::from_reader(reader)?;
+        ensure!(
+            snapshot.version == SNAPSHOT_VERSION,
+            "unsupported snapshot version {}, expected {SNAPSHOT_VERSION}",
+            snapshot.version
+        );
+        ensure!(
+            !snapshot.layers.is_empty(),
+            "snapshots must hold at least one layer"
+        );
+
+        let layers = snapshot
+            .layers
+            .into_iter()
+            .map(|layer| {
+                let cells = layer
+                    .cells
+                    .decode(layer.neighbor_ctx.n_cells())
+                    .with_context(|| format!("invalid cells for layer '{}'", layer.name))?;
+                let grid = Grid::from_parts(layer.neighbor_ctx, cells, layer.initialization)
+                    .with_context(|| format!("invalid grid for layer '{}'", layer.name))?;
+
+                Ok(Layer::new(layer.name, layer.model, grid))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ensure!(
+            layers
+                .iter()
+                .all(|layer| layer.grid.same_lattice(&layers[0].grid)),
+            "every layer must have the same dimensions"
+        );
+
+        Ok(Self {
+            layers,
+            scratch: StateMap::new(),
+            generation: snapshot.generation,
+            update_scheme: snapshot.update_scheme,
+            seed: snapshot.seed,
+            rng: snapshot.rng,
+            clocks: snapshot.clocks,
+            conservation_check: snapshot.conservation_check,
+            history: None,
+            statistics: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grid::{
+        neighbor_strategy::{Boundary, NeighboringStrategy},
+        test_utils::game_of_life_soup,
+    };
+    use rstest::rstest;
+
+    #[test]
+    fn run_lengths_should_round_trip() {
+        let cells = [0, 0, 0, 1, 1, 0, 2].map(NodeId::from_index);
+
+        let encoded = RunLengths::encode(&cells);
+
+        assert_eq!(encoded, RunLengths(vec![(0, 3), (1, 2), (0, 1), (2, 1)]));
+        assert_eq!(encoded.decode(7).unwrap(), cells);
+    }
+
+    #[rstest]
+    #[case(vec![(0, 3), (1, 3)])]
+    #[case(vec![(0, 8), (1, 3)])]
+    #[case(vec![(0, usize::MAX)])]
+    #[case(vec![(0, usize::MAX), (1, 8)])]
+    fn run_lengths_should_add_up_before_decoding(#[case] runs: Vec<(usize, usize)>) {
+        assert!(RunLengths(runs).decode(7).is_err());
+    }
+
+    #[rstest]
+    #[case(UpdateScheme::Synchronous)]
+    #[case(UpdateScheme::RandomSequential)]
+    #[case(UpdateScheme::RandomIndependent { probability: 0.5 })]
+    #[case(UpdateScheme::Clocked { min_period: 1, max_period: 4 })]
+    fn loaded_snapshots_should_resume_identically(#[case] update_scheme: UpdateScheme) {
+        let grid = game_of_life_soup(24, 16, 0.4, 11);
+
+        let mut original = SimulationContext::new(Model::game_of_life(), grid);
+        original.set_update_scheme(update_scheme);
+        original.reseed(5);
+        (0..3).for_each(|_| original.step());
+
+        let mut saved = Vec::new();
+        original.save_snapshot(&mut saved).unwrap();
+        let mut resumed = SimulationContext::load_snapshot(saved.as_slice()).unwrap();
+
+        assert_eq!(resumed.generation(), 3);
+        assert_eq!(resumed.grid().boundary(), Boundary::Toroidal);
+        assert_eq!(
+            resumed.grid().initialization(),
+            original.grid().initialization()
+        );
+        for _ in 0..5 {
+            original.step();
+            resumed.step();
+            assert_eq!(resumed.grid().cells(), original.grid().cells());
+        }
+    }
+
+    #[test]
+    fn snapshots_should_be_validated() {
+        let ctx = SimulationContext::new(
+            Model::game_of_life(),
+            Grid::empty(4, 2, NeighboringStrategy::SquareAndCorners),
+        );
+        let mut saved = Vec::new();
+        ctx.save_snapshot(&mut saved).unwrap();
+        let saved = String::from_utf8(saved).unwrap();
+
+        let future = saved.replace("version:1", "version:2");
+        assert!(SimulationContext::load_snapshot(future.as_bytes()).is_err());
+
+        let truncated = saved.replace("cells:([(0,4)])", "cells:([(0,3)])");
+        assert_ne!(truncated, saved);
+        assert!(SimulationContext::load_snapshot(truncated.as_bytes()).is_err());
+
+        let inflated = saved.replace("cells:([(0,4)])", &format!("cells:([(0,{})])", usize::MAX));
+        assert_ne!(inflated, saved);
+        assert!(SimulationContext::load_snapshot(inflated.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{collections::BTreeMap, io::Write};
+
+use serde_json::json;
+
+use crate::model::{EdgeId, NodeId};
+
+use super::SimulationContext;
+
+/// Time series of the main layer, one record per generation, as gathered
+/// by [`SimulationContext::enable_statistics`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Statistics {
+    state_names: BTreeMap<NodeId, String>,
+    edge_names: BTreeMap<EdgeId, String>,
+    records: Vec<GenerationStatistics>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenerationStatistics {
+    pub generation: u64,
+    /// Cells in each state, once the generation was reached
+    pub population: BTreeMap<NodeId, usize>,
+    /// How many cells each edge took out of their state on the way to the
+    /// generation. Cells skipped by the update scheme fire nothing.
+    pub firings: BTreeMap<EdgeId, u64>,
+}
+
+/// Gathers [`Statistics`] as the run steps. Edges are counted while cells
+/// are evaluated, by each band of the grid on its own, then added up here.
+pub(super) struct StatisticsCollector {
+    statistics: Statistics,
+    /// Firings of each edge of the main layer, by index in its model
+    pub(super) firings: Vec<u64>,
+}
+
+impl StatisticsCollector {
+    /// Makes room to count the edges the main layer currently has
+    pub(super) fn before_step(&mut self, ctx: &SimulationContext) {
+        let n_edges = ctx.model().all_edges().len();
+        self.firings.resize(n_edges, 0);
+    }
+
+    /// Adds up the firings of each edge counted apart
+    pub(super) fn add_firings(&mut self, firings: &[u64]) {
+        self.firings
+            .iter_mut()
+            .zip(firings)
+            .for_each(|(total, count)| *total += count);
+    }
+
+    pub(super) fn after_step(&mut self, ctx: &SimulationContext) {
+        let model = ctx.model();
+        let statistics = &mut self.statistics;
+        for (id, node) in model.nodes() {
+            statistics.state_names.insert(*id, node.name().to_string());
+        }
+        for edge in model.all_edges() {
+            statistics
+                .edge_names
+                .insert(*edge.id(), edge.name().to_string());
+        }
+
+        let firings = model
+            .all_edges()
+            .iter()
+            .zip(&mut self.firings)
+            .map(|(edge, count)| (*edge.id(), std::mem::take(count)))
+            .collect();
+        statistics.records.push(GenerationStatistics {
+            generation: ctx.generation(),
+            population: ctx.grid().population(),
+            firings,
+        });
+    }
+}
+
+impl Statistics {
+    #[inline]
+    pub fn records(&self) -> &[GenerationStatistics] {
+        &self.records
+    }
+
+    /// Writes one line per generation, with a column for the population of
+    /// each state then one for the firings of each edge, headed by their
+    /// names
+    pub fn write_csv(&self, mut writer: impl Write) -> anyhow::Result<()> {
+        let header: Vec<_> = std::iter::once("generation")
+            .chain(self.state_names.values().map(String::as_str))
+            .chain(self.edge_names.values().map(String::as_str))
+            .map(csv_field)
+            .collect();
+        writeln!(writer, "{}", header.join(","))?;
+
+        for record in &self.records {
+            let population = self
+                .state_names
+                .keys()
+                .map(|state| record.population.get(state).copied().unwrap_or_default() as u64);
+            let firings = self
+                .edge_names
+                .keys()
+                .map(|edge| record.firings.get(edge).copied().unwrap_or_default());
+            let row: Vec<_> = std::iter::once(record.generation)
+                .chain(population)
+                .chain(firings)
+                .map(|value| value.to_string())
+                .collect();
+            writeln!(writer, "{}", row.join(","))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes an array with an object per generation, where population and
+    /// firings are keyed by the names of states and edges
+    pub fn write_json(&self, writer: impl Write) -> anyhow::Result<()> {
+        let records: Vec<_> = self
+            .records
+            .iter()
+            .map(|record| {
+                let population: serde_json::Map<_, _> = self
+                    .state_names
+                    .iter()
+                    .map(|(state, name)| {
+                        let count = record.population.get(state).copied().unwrap_or_default();
+                        (name.clone(), count.into())
+                    })
+                    .collect();
+                let firings: serde_json::Map<_, _> = self
+                    .edge_names
+                    .iter()
+                    .map(|(edge, name)| {
+                        let count = record.firings.get(edge).copied().unwrap_or_default();
+                        (name.clone(), count.into())
+                    })
+                    .collect();
+
+                json!({
+                    "generation": record.generation,
+                    "population": population,
+                    "firings": firings,
+                })
+            })
+            .collect();
+
+        serde_json::to_writer(writer, &records)?;
+        Ok(())
+    }
+}
+
+/// Quotes fields that would otherwise be split or misread
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+impl SimulationContext {
+    /// Starts recording the population of every state and the firings of
+    /// every edge of the main layer, from the current generation on. Any
+    /// statistics gathered so far are dropped.
+    pub fn enable_statistics(&mut self) {
+        let mut collector = StatisticsCollector {
+            statistics: Statistics::default(),
+            firings: Vec::new(),
+        };
+        collector.before_step(self);
+        collector.after_step(self);
+        self.statistics = Some(collector);
+    }
+
+    #[inline]
+    pub fn statistics(&self) -> Option<&Statistics> {
+        self.statistics
+            .as_ref()
+            .map(|collector| &collector.statistics)
+    }
+
+    /// Stops recording, returning the statistics gathered so far
+    pub fn take_statistics(&mut self) -> Option<Statistics> {
+        self.statistics.take().map(|collector| collector.statistics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        grid::{
+            neighbor_strategy::Boundary,
+            test_utils::{game_of_life_grid, game_of_life_soup},
+        },
+        model::{Edge, Model},
+        update_scheme::UpdateScheme,
+    };
+    use rstest::rstest;
+
+    const BLINKER: &str = include_str!("../../fixtures/gol/blinker.txt");
+
+    const BLOCK_AND_BLINKER: &str = "
+        ░░░░░░░░░░
+        ░░░░░░░░░░
+        ░░██░░░█░░
+        ░░██░░░█░░
+        ░░░░░░░█░░
+        ░░░░░░░░░░
+        ░░░░░░░░░░
+    ";
+
+    fn blinker_statistics() -> Statistics {
+        let mut ctx = SimulationContext::new(Model::game_of_life(), game_of_life_grid(BLINKER));
+        ctx.enable_statistics();
+        ctx.step();
+        ctx.step();
+
+        ctx.take_statistics().unwrap()
+    }
+
+    #[test]
+    fn statistics_should_count_states_and_firings() {
+        let statistics = blinker_statistics();
+        let records = statistics.records();
+
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].generation, 0);
+        assert_eq!(records[0].firings.values().sum::<u64>(), 0);
+        assert_eq!(
+            records[1].population,
+            [(NodeId(0), 22), (NodeId(1), 3)].into()
+        );
+        // Both ends of the blinker die, two of its sides are born
+        assert_eq!(
+            records[1].firings,
+            [(EdgeId(0), 2), (EdgeId(1), 0), (EdgeId(2), 2)].into()
+        );
+    }
+
+    #[test]
+    fn statistics_should_be_headed_by_names() {
+        let statistics = blinker_statistics();
+
+        let mut csv = Vec::new();
+        statistics.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "generation,Dead,Alive,Underpopulation,Overpopulation,Reproduction\n\
+             0,22,3,0,0,0\n\
+             1,22,3,2,0,2\n\
+             2,22,3,2,0,2\n"
+        );
+
+        let mut json = Vec::new();
+        statistics.write_json(&mut json).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json[1]["population"]["Alive"], 3);
+        assert_eq!(json[2]["firings"]["Reproduction"], 2);
+        assert_eq!(csv_field("Born, \"again\""), "\"Born, \"\"again\"\"\"");
+    }
+
+    #[rstest]
+    #[case::bit_packed(Boundary::Open)]
+    #[case::skipping_quiescent_cells(Boundary::Reflective)]
+    fn statistics_should_count_edges_looping_back(#[case] boundary: Boundary) {
+        let mut model = Model::game_of_life();
+        model.add_edge(Edge::new("Survival".to_string(), NodeId(1), NodeId(1)));
+        let mut grid = game_of_life_grid(BLOCK_AND_BLINKER);
+        grid.set_boundary(boundary);
+
+        let mut ctx = SimulationContext::new(model, grid);
+        ctx.enable_statistics();
+        (0..4).for_each(|_| ctx.step());
+        assert_eq!(ctx.grid().is_bit_packed(), boundary == Boundary::Open);
+
+        let statistics = ctx.take_statistics().unwrap();
+        for record in &statistics.records()[1..] {
+            // The block and the middle of the blinker survive
+            assert_eq!(
+                record.firings,
+                [
+                    (EdgeId(0), 2),
+                    (EdgeId(1), 0),
+                    (EdgeId(2), 2),
+                    (EdgeId(3), 5)
+                ]
+                .into()
+            );
+        }
+    }
+
+    #[test]
+    fn statistics_should_leave_the_run_untouched() {
+        let run = |update_scheme, with_statistics| {
+            let grid = game_of_life_soup(32, 32, 0.3, 4);
+
+            let mut ctx = SimulationContext::new(Model::game_of_life(), grid);
+            ctx.set_update_scheme(update_scheme);
+            ctx.reseed(2);
+            if with_statistics {
+                ctx.enable_statistics();
+            }
+            (0..6).for_each(|_| ctx.step());
+            (ctx.grid().cells().to_vec(), ctx.take_statistics())
+        };
+
+        for update_scheme in [UpdateScheme::Synchronous, UpdateScheme::RandomSequential] {
+            let (cells, statistics) = run(update_scheme, true);
+            assert_eq!(cells, run(update_scheme, false).0);
+
+            let statistics = statistics.unwrap();
+            let last = statistics.records().last().unwrap();
+            let alive = cells.iter().filter(|cell| **cell == NodeId(1)).count();
+            assert_eq!(last.population[&NodeId(1)], alive);
+            assert!(last.firings.values().sum::<u64>() > 0);
+        }
+    }
+}
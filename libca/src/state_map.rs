@@ -1,107 +1,82 @@
-use crate::{model::NodeId, AVAILABLE_PARALLELISM};
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
-
-const DEFAULT_NUMBER_OF_DIFFERENT_STATES_EXPECTED: usize = 64;
-
-pub struct StatePool(Vec<StateMap>);
-
-impl StatePool {
-    pub fn new() -> Self {
-        let mut state_pool = Vec::with_capacity(*AVAILABLE_PARALLELISM);
-
-        for _ in 0..state_pool.capacity() {
-            state_pool.push(StateMap::new());
-        }
-
-        Self(state_pool)
-    }
-
-    pub fn get(&self, idx: usize) -> &StateMap {
-        let idx = idx % self.0.len();
-        &self.0[idx]
-    }
-}
-
-impl Default for StatePool {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-#[derive(Debug)]
+use crate::model::NodeId;
+
+/// How many of a cell's neighbors are in each state.
+///
+/// Maps are meant to be reused from one cell to the next, so each worker
+/// should own one. They start out with room for as many states as the model
+/// has, and grow to fit any larger state they come across. Counters are wide
+/// enough for neighborhoods of any radius a grid could hold.
+#[derive(Debug, Clone, Default)]
 pub struct StateMap {
-    ptr: *mut u8,
-    len: usize,
-    cap: usize,
+    counts: Vec<u32>,
 }
 
 impl StateMap {
     pub fn new() -> Self {
-        let (ptr, len, cap) = vec![0; DEFAULT_NUMBER_OF_DIFFERENT_STATES_EXPECTED].into_raw_parts();
-        Self { ptr, len, cap }
+        Self::default()
     }
 
-    pub fn count_states(&self, states: impl Iterator<Item = NodeId>) {
-        // # Safety
-        // This is safe so long as:
-        // 1. `self.ptr` is not null
-        // 2. `self.ptr + self.len - 1` is indexable
-        // This should hold true so long as `self.ptr` and `self.len` are only
-        // attributed to once
-        let slice = unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) };
+    /// Map with room for states `0..n_states` up front
+    pub fn with_states(n_states: usize) -> Self {
+        Self {
+            counts: vec![0; n_states],
+        }
+    }
 
-        // Clear vec; all states have 0 count
-        slice.par_iter_mut().for_each(|entry| *entry = 0);
+    /// Replaces the current counts with those of `states`
+    pub fn count_states(&mut self, states: impl Iterator<Item = NodeId>) {
+        self.counts.fill(0);
 
-        states
-            .into_iter()
-            .map(NodeId::as_index)
-            .for_each(|idx| slice[idx] += 1);
+        for idx in states.map(NodeId::as_index) {
+            if idx >= self.counts.len() {
+                self.counts.resize(idx + 1, 0);
+            }
+            self.counts[idx] += 1;
+        }
     }
 
+    /// How many neighbors are in `state`, which is 0 for states never seen
     #[inline]
-    pub fn get_count(&self, state: NodeId) -> u8 {
-        let idx = state.as_index();
-        assert!(
-            idx < self.len,
-            "Tried indexing a larger state than we had space for"
-        );
-
-        // # Safety
-        // This is safe so long as:
-        // 1. `self.ptr` is not null
-        //  - This is the case considering we only ever write to `self.ptr` once,
-        //    from a `Vec::into_raw_parts` call
-        // 2. `state` is less than `self.len`
-        //  - This is the assertion above
-        unsafe { *self.ptr.add(idx) }
+    pub fn get_count(&self, state: NodeId) -> u32 {
+        self.counts
+            .get(state.as_index())
+            .copied()
+            .unwrap_or_default()
     }
 
-    pub const fn default_size() -> usize {
-        size_of::<Self>() * DEFAULT_NUMBER_OF_DIFFERENT_STATES_EXPECTED
+    /// Number of states the map currently has room for
+    #[inline]
+    pub fn n_states(&self) -> usize {
+        self.counts.len()
     }
 }
 
-// # Safety
-// StateMap should only be used to collect temporary data. For such purposes,
-// it features interior mutability
-unsafe impl Send for StateMap {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state_map_should_grow_past_its_initial_size() {
+        let mut state_map = StateMap::with_states(2);
 
-unsafe impl Sync for StateMap {}
+        state_map.count_states([NodeId(1), NodeId(100), NodeId(100)].into_iter());
 
-impl Default for StateMap {
-    fn default() -> Self {
-        Self::new()
+        assert_eq!(state_map.get_count(NodeId(1)), 1);
+        assert_eq!(state_map.get_count(NodeId(100)), 2);
+        assert_eq!(state_map.get_count(NodeId(1000)), 0);
+        assert_eq!(state_map.n_states(), 101);
     }
-}
 
-impl Drop for StateMap {
-    fn drop(&mut self) {
-        // # Safety
-        // This assumes `self.ptr`, `self.len` and `self.cap`
-        // were handed off from a `Vec::into_raw_parts`
-        unsafe {
-            let _ = Vec::from_raw_parts(self.ptr, self.len, self.cap);
-        }
+    #[test]
+    fn state_map_should_count_large_neighborhoods() {
+        let mut state_map = StateMap::with_states(2);
+
+        state_map.count_states(std::iter::repeat_n(NodeId(1), 1000));
+        assert_eq!(state_map.get_count(NodeId(1)), 1000);
+
+        // Counts start over for every cell
+        state_map.count_states(std::iter::repeat_n(NodeId(0), 3));
+        assert_eq!(state_map.get_count(NodeId(0)), 3);
+        assert_eq!(state_map.get_count(NodeId(1)), 0);
     }
 }
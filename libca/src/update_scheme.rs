@@ -0,0 +1,50 @@
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+/// How cells are picked for updating within a single step.
+///
+/// Block models always step their whole Margolus partition at once, so they
+/// ignore any scheme other than [`UpdateScheme::Synchronous`].
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+pub enum UpdateScheme {
+    /// Every cell updates at once from the previous generation
+    #[default]
+    Synchronous,
+    /// Cells are picked at random, one at a time, and updated in place. A
+    /// step picks as many cells as there are in the grid, so some may update
+    /// more than once and some not at all.
+    RandomSequential,
+    /// Cells are updated in place, one at a time, in reading order
+    LineSweep,
+    /// Each cell updates with the given probability, and those that do update
+    /// synchronously. Probabilities outside `0.0..=1.0` are clamped, and NaN
+    /// updates no cell.
+    RandomIndependent { probability: f64 },
+    /// Each cell has its own clock, ticking every `min_period..=max_period`
+    /// steps with a random phase. Cells whose clocks tick update
+    /// synchronously.
+    Clocked { min_period: u32, max_period: u32 },
+}
+
+/// Per-cell clock used by [`UpdateScheme::Clocked`]
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) struct CellClock {
+    period: u32,
+    phase: u32,
+}
+
+impl CellClock {
+    pub(crate) fn random(rng: &mut ChaCha8Rng, min_period: u32, max_period: u32) -> Self {
+        let min_period = min_period.max(1);
+        let period = rng.random_range(min_period..=max_period.max(min_period));
+        let phase = rng.random_range(0..period);
+
+        Self { period, phase }
+    }
+
+    #[inline]
+    pub(crate) fn ticks_at(self, generation: u64) -> bool {
+        (generation + self.phase as u64).is_multiple_of(self.period as u64)
+    }
+}